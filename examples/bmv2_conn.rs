@@ -26,6 +26,7 @@ pub async fn main() {
                 election_id_high: 0,
                 election_id_low: 1,
             }),
+            ..Default::default()
         },
     )
     .await
@@ -115,6 +115,7 @@ impl Handler<Bmv2Event> for Forward {
                     .await
                     .unwrap();
             }
+            _ => {}
         }
     }
 }
@@ -143,6 +144,7 @@ pub async fn main() {
                     election_id_high: 0,
                     election_id_low: 1,
                 }),
+                ..Default::default()
             },
             pipeconf,
         )
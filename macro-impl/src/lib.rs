@@ -31,10 +31,22 @@ enum _FlowMatchValue {
     Range(Box<Expr>, Box<Expr>),
     Lpm(Box<Expr>, Box<Expr>),
     Ternary(Box<Expr>, Box<Expr>),
+    Optional(Expr),
+    DontCare,
 }
 
 impl Parse for _FlowMatchValue {
     fn parse(input: &ParseBuffer) -> Result<Self> {
+        // optional
+        if input.peek(Token![?]) {
+            input.parse::<Token![?]>()?;
+            return Ok(_FlowMatchValue::Optional(input.parse()?));
+        }
+        // don't care
+        if input.peek(Token![_]) {
+            input.parse::<Token![_]>()?;
+            return Ok(_FlowMatchValue::DontCare);
+        }
         let expr: Expr = input.parse()?;
         match expr {
             // range
@@ -160,7 +172,7 @@ fn flow_match_to_quotes(flow_match: _FlowMatch) -> proc_macro2::TokenStream {
             _FlowMatchValue::Exact(expr) => {
                 quotes.push(quote! {
                     rusty_p4::util::flow::FlowMatch {
-                        name: #name,
+                        name: std::borrow::Cow::Borrowed(#name),
                         value: rusty_p4::util::value::EXACT(#expr)
                     }
                 });
@@ -168,7 +180,7 @@ fn flow_match_to_quotes(flow_match: _FlowMatch) -> proc_macro2::TokenStream {
             _FlowMatchValue::Range(from, two) => {
                 quotes.push(quote! {
                     rusty_p4::util::flow::FlowMatch {
-                        name: #name,
+                        name: std::borrow::Cow::Borrowed(#name),
                         value: rusty_p4::util::value::RANGE(#from,#two)
                     }
                 });
@@ -176,7 +188,7 @@ fn flow_match_to_quotes(flow_match: _FlowMatch) -> proc_macro2::TokenStream {
             _FlowMatchValue::Lpm(v, lpm) => {
                 quotes.push(quote! {
                     rusty_p4::util::flow::FlowMatch {
-                        name: #name,
+                        name: std::borrow::Cow::Borrowed(#name),
                         value: rusty_p4::util::value::LPM(#v,#lpm)
                     }
                 });
@@ -184,18 +196,34 @@ fn flow_match_to_quotes(flow_match: _FlowMatch) -> proc_macro2::TokenStream {
             _FlowMatchValue::Ternary(v, t) => {
                 quotes.push(quote! {
                     rusty_p4::util::flow::FlowMatch {
-                        name: #name,
+                        name: std::borrow::Cow::Borrowed(#name),
                         value: rusty_p4::util::value::TERNARY(#v,#t)
                     }
                 });
             }
+            _FlowMatchValue::Optional(v) => {
+                quotes.push(quote! {
+                    rusty_p4::util::flow::FlowMatch {
+                        name: std::borrow::Cow::Borrowed(#name),
+                        value: rusty_p4::util::value::OPTIONAL(#v)
+                    }
+                });
+            }
+            _FlowMatchValue::DontCare => {
+                quotes.push(quote! {
+                    rusty_p4::util::flow::FlowMatch {
+                        name: std::borrow::Cow::Borrowed(#name),
+                        value: rusty_p4::util::value::InnerValue::DONT_CARE
+                    }
+                });
+            }
         }
     }
 
     if let Some(d) = flow_match.default {
         quote! {{
             let mut _v: rusty_p4::util::SmallVec<[rusty_p4::util::flow::FlowMatch;3]> = rusty_p4::util::smallvec![#(#quotes),*];
-            _v.sort_by(|a,b|a.name.cmp(b.name));
+            _v.sort_by(|a,b|a.name.cmp(&b.name));
             rusty_p4::util::flow::merge_matches(&mut _v, &#d);
             let _t = std::sync::Arc::new(_v);
             _t
@@ -203,7 +231,7 @@ fn flow_match_to_quotes(flow_match: _FlowMatch) -> proc_macro2::TokenStream {
     } else {
         quote! {{
             let mut _v:rusty_p4::util::SmallVec<[rusty_p4::util::flow::FlowMatch;3]> = rusty_p4::util::smallvec![#(#quotes),*];
-            _v.sort_by(|a,b|a.name.cmp(b.name));
+            _v.sort_by(|a,b|a.name.cmp(&b.name));
             let _t = std::sync::Arc::new(_v);
             _t
         }}
@@ -226,9 +254,12 @@ struct _Flow {
     pipe: Option<String>,
     table: String,
     table_match: _FlowMatch,
-    action_name: String,
+    action_name: Option<String>,
     action_parameters: Option<Punctuated<_FlowActionItem, Token![,]>>,
+    member: Option<Expr>,
+    group: Option<Expr>,
     priority: Option<Expr>,
+    idle_timeout: Option<Expr>,
 }
 
 impl Parse for _Flow {
@@ -239,6 +270,9 @@ impl Parse for _Flow {
         let mut action = None;
         let mut action_params = None;
         let mut priority = None;
+        let mut idle_timeout = None;
+        let mut member = None;
+        let mut group = None;
         while !input.is_empty() {
             let field_name = input.parse::<Ident>()?.to_string();
             match field_name.as_ref() {
@@ -289,6 +323,28 @@ impl Parse for _Flow {
                     let p = input.parse::<Expr>()?;
                     priority = Some(p);
                 }
+                "member" => {
+                    if member.is_some() {
+                        return Err(input.error("Duplicated member field"));
+                    }
+                    input.parse::<Token![:]>()?;
+                    member = Some(input.parse::<Expr>()?);
+                }
+                "group" => {
+                    if group.is_some() {
+                        return Err(input.error("Duplicated group field"));
+                    }
+                    input.parse::<Token![:]>()?;
+                    group = Some(input.parse::<Expr>()?);
+                }
+                "idle_timeout" => {
+                    if idle_timeout.is_some() {
+                        return Err(input.error("Duplicated idle_timeout field"));
+                    }
+                    input.parse::<Token![:]>()?;
+                    let t = input.parse::<Expr>()?;
+                    idle_timeout = Some(t);
+                }
                 other => {
                     unimplemented!("Unsupported flow field {}", other);
                 }
@@ -300,13 +356,21 @@ impl Parse for _Flow {
                 break;
             }
         }
+        match (action.is_some(), member.is_some(), group.is_some()) {
+            (false, false, false) => return Err(input.error("Missing action field")),
+            (true, false, false) | (false, true, false) | (false, false, true) => {}
+            _ => return Err(input.error("Only one of action, member and group fields is allowed")),
+        }
         Ok(Self {
             pipe,
             table: table.ok_or(input.error("Missing table field"))?,
             table_match: table_matches.ok_or(input.error("Missing match field"))?,
-            action_name: action.ok_or(input.error("Missing action field"))?,
+            action_name: action,
             action_parameters: action_params,
+            member,
+            group,
             priority,
+            idle_timeout,
         })
     }
 }
@@ -321,7 +385,7 @@ fn action_params_to_quote(
             let expr = m.value;
             quotes.push(quote! {
                 rusty_p4::util::flow::FlowActionParam {
-                    name: #name,
+                    name: std::borrow::Cow::Borrowed(#name),
                     value: rusty_p4::util::value::encode(#expr)
                 }
             });
@@ -358,29 +422,44 @@ pub fn flow(input: TokenStream) -> TokenStream {
         .as_ref()
         .map(|pipe| format!("{}.{}", pipe, &flow.table))
         .unwrap_or(flow.table.clone());
-    let action_name = if flow.action_name == "NoAction" {
-        flow.action_name
+    let action = if let Some(member) = flow.member {
+        quote!(rusty_p4::util::flow::FlowTableAction::Member(#member))
+    } else if let Some(group) = flow.group {
+        quote!(rusty_p4::util::flow::FlowTableAction::Group(#group))
     } else {
-        flow.pipe
-            .as_ref()
-            .map(|pipe| format!("{}.{}", pipe, flow.action_name))
-            .unwrap_or(flow.action_name)
+        let action_name = flow.action_name.unwrap_or_default();
+        let action_name = if action_name == "NoAction" {
+            action_name
+        } else {
+            flow.pipe
+                .as_ref()
+                .map(|pipe| format!("{}.{}", pipe, action_name))
+                .unwrap_or(action_name)
+        };
+        let action_params = action_params_to_quote(flow.action_parameters);
+        quote! {
+            rusty_p4::util::flow::FlowTableAction::Action(rusty_p4::util::flow::FlowAction {
+                name:std::borrow::Cow::Borrowed(#action_name),
+                params:#action_params
+            })
+        }
     };
     let flow_matches = flow_match_to_quotes(flow.table_match);
-    let action_params = action_params_to_quote(flow.action_parameters);
     let priority = flow.priority.map(|expr| quote!(#expr)).unwrap_or(quote!(1));
+    let idle_timeout = flow
+        .idle_timeout
+        .map(|expr| quote!(#expr))
+        .unwrap_or(quote!(0));
     TokenStream::from(quote! {
         rusty_p4::util::flow::Flow {
             table: rusty_p4::util::flow::FlowTable {
-                name:#flow_table_name,
+                name:std::borrow::Cow::Borrowed(#flow_table_name),
                 matches:#flow_matches
             },
-            action: rusty_p4::util::flow::FlowAction {
-                name:#action_name,
-                params:#action_params
-            },
+            action: #action,
             priority:#priority,
-            metadata:0
+            metadata:0,
+            idle_timeout_ns:#idle_timeout
         }
     })
 }
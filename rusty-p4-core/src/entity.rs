@@ -1,13 +1,19 @@
-use crate::p4rt::pipeconf::DefaultPipeconf;
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
 
+pub mod action_profile;
 pub mod clone_session;
 pub mod counter;
+pub mod digest;
+pub mod direct_counter;
+pub mod direct_meter;
 pub mod meter;
 pub mod multicast_group;
-//pub mod direct_counter;
+mod proto_serde;
+pub mod register;
 pub type ProtoEntity = crate::proto::p4runtime::Entity;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum UpdateType {
     Insert,
     Modify,
@@ -27,5 +33,5 @@ impl Into<crate::proto::p4runtime::update::Type> for UpdateType {
 }
 
 pub trait ToEntity {
-    fn to_proto_entity(&self, pipeconf: &DefaultPipeconf) -> Option<ProtoEntity>;
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError>;
 }
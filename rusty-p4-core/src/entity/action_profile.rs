@@ -0,0 +1,85 @@
+use crate::entity::{ProtoEntity, ToEntity};
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use crate::p4rt::pure::build_action;
+use crate::util::flow::{FlowAction, FlowName};
+
+/// A member of an action profile, referenced by table entries with
+/// [FlowTableAction::Member](crate::util::flow::FlowTableAction::Member) or by groups.
+#[derive(Clone, Debug)]
+pub struct ActionProfileMember {
+    pub action_profile: FlowName,
+    pub member_id: u32,
+    pub action: FlowAction,
+}
+
+impl ToEntity for ActionProfileMember {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        Ok(ProtoEntity {
+            entity: Some(
+                crate::proto::p4runtime::entity::Entity::ActionProfileMember(
+                    crate::proto::p4runtime::ActionProfileMember {
+                        action_profile_id: action_profile_id(pipeconf, &self.action_profile)?,
+                        member_id: self.member_id,
+                        action: build_action(pipeconf, &self.action)?,
+                    },
+                ),
+            ),
+        })
+    }
+}
+
+/// A group of an action selector, referenced by table entries with
+/// [FlowTableAction::Group](crate::util::flow::FlowTableAction::Group).
+/// The members must be inserted before the group.
+#[derive(Clone, Debug)]
+pub struct ActionProfileGroup {
+    pub action_profile: FlowName,
+    pub group_id: u32,
+    pub members: Vec<ActionProfileGroupMember>,
+    /// max number of members in the group, 0 means the `max_group_size` in P4Info.
+    pub max_size: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ActionProfileGroupMember {
+    pub member_id: u32,
+    pub weight: i32,
+    /// the port watched for liveness, the member is not used if the port is down.
+    pub watch: i32,
+}
+
+impl ToEntity for ActionProfileGroup {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        Ok(ProtoEntity {
+            entity: Some(crate::proto::p4runtime::entity::Entity::ActionProfileGroup(
+                crate::proto::p4runtime::ActionProfileGroup {
+                    action_profile_id: action_profile_id(pipeconf, &self.action_profile)?,
+                    group_id: self.group_id,
+                    members: self
+                        .members
+                        .iter()
+                        .map(
+                            |member| crate::proto::p4runtime::action_profile_group::Member {
+                                member_id: member.member_id,
+                                weight: member.weight,
+                                watch: member.watch,
+                            },
+                        )
+                        .collect(),
+                    max_size: self.max_size,
+                },
+            )),
+        })
+    }
+}
+
+fn action_profile_id(pipeconf: &dyn Pipeconf, name: &str) -> Result<u32, ValidationError> {
+    pipeconf
+        .get_p4info_index()
+        .action_profile_id(name)
+        .ok_or_else(|| ValidationError::UnknownName {
+            kind: "action profile",
+            name: name.to_owned(),
+        })
+}
@@ -1,14 +1,37 @@
 use crate::entity::{ProtoEntity, ToEntity};
-use crate::p4rt::pipeconf::DefaultPipeconf;
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use serde::{Deserialize, Serialize};
 
-pub type CloneSession = crate::proto::p4runtime::CloneSessionEntry;
-pub type Replica = crate::proto::p4runtime::Replica;
+pub use super::multicast_group::Replica;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CloneSession {
+    pub session_id: u32,
+    pub replicas: Vec<Replica>,
+    #[serde(default)]
+    pub class_of_service: u32,
+    /// truncate cloned packets to this length, 0 means no truncation.
+    #[serde(default)]
+    pub packet_length_bytes: i32,
+}
+
+impl CloneSession {
+    fn into_proto(self) -> crate::proto::p4runtime::CloneSessionEntry {
+        crate::proto::p4runtime::CloneSessionEntry {
+            session_id: self.session_id,
+            replicas: self.replicas.into_iter().map(|r| r.into_proto()).collect(),
+            class_of_service: self.class_of_service,
+            packet_length_bytes: self.packet_length_bytes,
+        }
+    }
+}
 
 impl ToEntity for CloneSession {
-    fn to_proto_entity(&self, pipeconf: &DefaultPipeconf) -> Option<ProtoEntity> {
-        Some(ProtoEntity {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        Ok(ProtoEntity {
             entity: Some(crate::proto::p4runtime::entity::Entity::PacketReplicationEngineEntry(crate::proto::p4runtime::PacketReplicationEngineEntry {
-                r#type:Some(crate::proto::p4runtime::packet_replication_engine_entry::Type::CloneSessionEntry(self.clone()))
+                r#type:Some(crate::proto::p4runtime::packet_replication_engine_entry::Type::CloneSessionEntry(self.clone().into_proto()))
             }))
         })
     }
@@ -1,18 +1,22 @@
 use crate::entity::{ProtoEntity, ToEntity};
-use crate::p4rt::pipeconf::DefaultPipeconf;
-use crate::p4rt::pure::get_counter_id;
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::{DefaultPipeconf, Pipeconf};
 use crate::representation::DeviceID;
+use crate::util::flow::FlowName;
 use rusty_p4_proto::proto::v1::{CounterEntry, Entity, Index};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Counter {
-    pub name: &'static str,
+    pub name: FlowName,
+    /// `None` to read all cells of the counter.
+    #[serde(default)]
     pub index: Option<i64>,
 }
 
 impl Counter {
     pub fn to_index(&self, device: DeviceID, pipeconf: &DefaultPipeconf) -> Option<CounterIndex> {
-        let id = get_counter_id(pipeconf.get_p4info(), self.name)?;
+        let id = pipeconf.get_p4info_index().counter_id(&self.name)?;
         Some(CounterIndex {
             device,
             id,
@@ -22,9 +26,15 @@ impl Counter {
 }
 
 impl ToEntity for Counter {
-    fn to_proto_entity(&self, pipeconf: &DefaultPipeconf) -> Option<Entity> {
-        let id = get_counter_id(pipeconf.get_p4info(), self.name)?;
-        Some(ProtoEntity {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<Entity, ValidationError> {
+        let id = pipeconf
+            .get_p4info_index()
+            .counter_id(&self.name)
+            .ok_or_else(|| ValidationError::UnknownName {
+                kind: "counter",
+                name: self.name.to_string(),
+            })?;
+        Ok(ProtoEntity {
             entity: Some(crate::proto::p4runtime::entity::Entity::CounterEntry(
                 CounterEntry {
                     counter_id: id,
@@ -60,3 +70,11 @@ impl CounterIndex {
         }
     }
 }
+
+/// The value of one cell of an indirect counter.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct CounterValue {
+    pub index: i64,
+    pub packets: i64,
+    pub bytes: i64,
+}
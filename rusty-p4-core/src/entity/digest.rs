@@ -0,0 +1,41 @@
+use crate::entity::{ProtoEntity, ToEntity};
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use crate::util::flow::FlowName;
+use rusty_p4_proto::proto::v1::digest_entry;
+
+/// The config of a digest, which must be inserted before the device sends any digest list.
+#[derive(Clone, Debug)]
+pub struct DigestEntry {
+    pub name: FlowName,
+    /// max time the device waits before sending a digest list.
+    pub max_timeout_ns: i64,
+    /// max number of digest messages in a digest list, 0 means no limit.
+    pub max_list_size: i32,
+    /// time the device waits for an ack before sending the same digest messages again.
+    pub ack_timeout_ns: i64,
+}
+
+impl ToEntity for DigestEntry {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        let digest_id = pipeconf
+            .get_p4info_index()
+            .digest_id(&self.name)
+            .ok_or_else(|| ValidationError::UnknownName {
+                kind: "digest",
+                name: self.name.to_string(),
+            })?;
+        Ok(ProtoEntity {
+            entity: Some(crate::proto::p4runtime::entity::Entity::DigestEntry(
+                crate::proto::p4runtime::DigestEntry {
+                    digest_id,
+                    config: Some(digest_entry::Config {
+                        max_timeout_ns: self.max_timeout_ns,
+                        max_list_size: self.max_list_size,
+                        ack_timeout_ns: self.ack_timeout_ns,
+                    }),
+                },
+            )),
+        })
+    }
+}
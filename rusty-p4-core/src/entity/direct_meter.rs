@@ -0,0 +1,29 @@
+use crate::entity::{ProtoEntity, ToEntity};
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use crate::util::flow::Flow;
+use rusty_p4_proto::proto::v1::{DirectMeterEntry, MeterConfig};
+use serde::{Deserialize, Serialize};
+
+/// The direct meter attached to the table entry of a flow.
+/// The entry is found by [Flow::to_table_entry_match].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirectMeter {
+    pub flow: Flow,
+    /// the meter config to write, as in [Meter::config](super::meter::Meter::config).
+    #[serde(default, with = "super::proto_serde::option_meter_config")]
+    pub config: Option<MeterConfig>,
+}
+
+impl ToEntity for DirectMeter {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        Ok(ProtoEntity {
+            entity: Some(crate::proto::p4runtime::entity::Entity::DirectMeterEntry(
+                DirectMeterEntry {
+                    table_entry: Some(self.flow.to_table_entry_match(pipeconf)?),
+                    config: self.config.clone(),
+                },
+            )),
+        })
+    }
+}
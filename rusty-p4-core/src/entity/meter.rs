@@ -1,31 +1,37 @@
 use crate::entity::{ProtoEntity, ToEntity};
-use crate::p4rt::pipeconf::DefaultPipeconf;
-use crate::p4rt::pure::get_meter_id;
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use crate::util::flow::FlowName;
 use rusty_p4_proto::proto::v1::{Index, MeterConfig, MeterEntry};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+/// An indirect meter. The rates and bursts of the config are in the meter unit
+/// declared in P4Info, see [`get_meter_unit`](crate::p4rt::pure::get_meter_unit).
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Meter {
-    pub name: &'static str,
-    pub index: i64,
-    pub cburst: i64,
-    pub cir: i64,
-    pub pburst: i64,
-    pub pir: i64,
+    pub name: FlowName,
+    /// `None` to read all cells of the meter.
+    #[serde(default)]
+    pub index: Option<i64>,
+    /// the meter config to write, `None` resets the meter to its default config, which lets all packets pass.
+    #[serde(default, with = "super::proto_serde::option_meter_config")]
+    pub config: Option<MeterConfig>,
 }
 
 impl ToEntity for Meter {
-    fn to_proto_entity(&self, pipeconf: &DefaultPipeconf) -> Option<ProtoEntity> {
-        Some(ProtoEntity {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        Ok(ProtoEntity {
             entity: Some(crate::proto::p4runtime::entity::Entity::MeterEntry(
                 MeterEntry {
-                    meter_id: get_meter_id(pipeconf.get_p4info(), self.name).unwrap(),
-                    index: Some(Index { index: self.index }),
-                    config: Some(MeterConfig {
-                        cir: self.cir,
-                        cburst: self.cburst,
-                        pir: self.pir,
-                        pburst: self.pburst,
-                    }),
+                    meter_id: pipeconf
+                        .get_p4info_index()
+                        .meter_id(&self.name)
+                        .ok_or_else(|| ValidationError::UnknownName {
+                            kind: "meter",
+                            name: self.name.to_string(),
+                        })?,
+                    index: self.index.map(|index| Index { index }),
+                    config: self.config.clone(),
                 },
             )),
         })
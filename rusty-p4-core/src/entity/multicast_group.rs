@@ -1,7 +1,9 @@
 use crate::entity::{ProtoEntity, ToEntity};
-use crate::p4rt::pipeconf::DefaultPipeconf;
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MulticastGroupEntry {
     pub multicast_group_id: u32,
     pub replicas: ::std::vec::Vec<Replica>,
@@ -16,14 +18,14 @@ impl MulticastGroupEntry {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Replica {
     pub egress_port: u32,
     pub instance: u32,
 }
 
 impl Replica {
-    fn into_proto(self) -> crate::proto::p4runtime::Replica {
+    pub(crate) fn into_proto(self) -> crate::proto::p4runtime::Replica {
         crate::proto::p4runtime::Replica {
             egress_port: self.egress_port,
             instance: self.instance,
@@ -32,8 +34,8 @@ impl Replica {
 }
 
 impl ToEntity for MulticastGroupEntry {
-    fn to_proto_entity(&self, pipeconf: &DefaultPipeconf) -> Option<ProtoEntity> {
-        Some(ProtoEntity {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        Ok(ProtoEntity {
             entity: Some(crate::proto::p4runtime::entity::Entity::PacketReplicationEngineEntry(crate::proto::p4runtime::PacketReplicationEngineEntry {
                 r#type:Some(crate::proto::p4runtime::packet_replication_engine_entry::Type::MulticastGroupEntry(self.clone().into_proto()))
             }))
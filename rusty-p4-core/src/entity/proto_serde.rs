@@ -0,0 +1,61 @@
+//! Serde of the p4runtime messages held by entities, which do not implement serde themselves.
+use crate::proto::p4runtime::{CounterData, MeterConfig};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MeterConfig")]
+struct MeterConfigDef {
+    cir: i64,
+    cburst: i64,
+    pir: i64,
+    pburst: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CounterData")]
+struct CounterDataDef {
+    byte_count: i64,
+    packet_count: i64,
+}
+
+pub(crate) mod option_meter_config {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        config: &Option<MeterConfig>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wrapper<'a>(#[serde(with = "MeterConfigDef")] &'a MeterConfig);
+        config.as_ref().map(Wrapper).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<MeterConfig>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "MeterConfigDef")] MeterConfig);
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(config)| config))
+    }
+}
+
+pub(crate) mod option_counter_data {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<CounterData>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wrapper<'a>(#[serde(with = "CounterDataDef")] &'a CounterData);
+        data.as_ref().map(Wrapper).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<CounterData>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "CounterDataDef")] CounterData);
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(data)| data))
+    }
+}
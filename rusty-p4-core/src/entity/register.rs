@@ -0,0 +1,36 @@
+use crate::entity::{ProtoEntity, ToEntity};
+use crate::error::ValidationError;
+use crate::p4rt::pipeconf::Pipeconf;
+use crate::util::flow::FlowName;
+use rusty_p4_proto::proto::v1::{Index, P4Data, RegisterEntry};
+
+/// A register cell, or the whole register array if `index` is `None`.
+/// Registers can only be read, or written with [UpdateType::Modify](crate::entity::UpdateType::Modify).
+#[derive(Clone, Debug)]
+pub struct Register {
+    pub name: FlowName,
+    pub index: Option<i64>,
+    /// the value to write, `None` when reading.
+    pub data: Option<P4Data>,
+}
+
+impl ToEntity for Register {
+    fn to_proto_entity(&self, pipeconf: &dyn Pipeconf) -> Result<ProtoEntity, ValidationError> {
+        let id = pipeconf
+            .get_p4info_index()
+            .register_id(&self.name)
+            .ok_or_else(|| ValidationError::UnknownName {
+                kind: "register",
+                name: self.name.to_string(),
+            })?;
+        Ok(ProtoEntity {
+            entity: Some(crate::proto::p4runtime::entity::Entity::RegisterEntry(
+                RegisterEntry {
+                    register_id: id,
+                    index: self.index.map(|index| Index { index }),
+                    data: self.data.clone(),
+                },
+            )),
+        })
+    }
+}
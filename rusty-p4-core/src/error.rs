@@ -1,3 +1,5 @@
+use crate::p4rt::api_version::ApiVersion;
+use crate::p4rt::batch::BatchItem;
 use crate::representation::DeviceID;
 use std::error::Error as StdError;
 use std::fmt::{Display, Formatter};
@@ -21,10 +23,84 @@ pub enum DeviceError {
     DeviceConfigFileError { path: String, error: std::io::Error },
     #[error("Master not acquired, {:?}", reason)]
     NotMaster { device: DeviceID, reason: String },
+    #[error("Device {:?} rejected {} update(s): {:?}", device, errors.len(), errors)]
+    DeviceWriteError {
+        device: DeviceID,
+        errors: Vec<UpdateError>,
+    },
+    #[error(
+        "Device {:?} does not support {}, which requires P4Runtime {} but the device reports {}",
+        device,
+        feature,
+        required,
+        version.map(|v| v.to_string()).unwrap_or_else(|| "no version".to_owned())
+    )]
+    Unsupported {
+        device: DeviceID,
+        feature: &'static str,
+        required: ApiVersion,
+        /// `None` if the device does not implement the Capabilities RPC.
+        version: Option<ApiVersion>,
+    },
     #[error("Device {:?} error: {}", device, error)]
     Other { device: DeviceID, error: String },
 }
 
+/// The error of one update in a failed p4runtime write.
+#[derive(Debug, Clone)]
+pub struct UpdateError {
+    /// Position of the update in the write request.
+    pub index: usize,
+    /// The flow or entity which the update was built from.
+    pub item: Option<BatchItem>,
+    pub code: tonic::Code,
+    pub message: String,
+    /// Target specific error space and code, e.g. bmv2 uses space "ALL-sswitch-p4org".
+    pub space: String,
+    pub space_code: i32,
+}
+
+/// A name, id or value that does not fit the P4Info of the pipeconf.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    #[error("{} '{}' not found in P4Info", kind, name)]
+    UnknownName { kind: &'static str, name: String },
+    #[error("{} id {} not found in P4Info", kind, id)]
+    UnknownId { kind: &'static str, id: u32 },
+    #[error("match field '{}' is {} but the value is {}", field, expected, actual)]
+    MatchKindMismatch {
+        field: String,
+        expected: String,
+        actual: &'static str,
+    },
+    #[error("value of '{}' is wider than its bitwidth {}", name, bitwidth)]
+    ValueTooWide { name: String, bitwidth: i32 },
+    #[error(
+        "prefix length {} of '{}' is out of range 0..={}",
+        prefix_len,
+        field,
+        bitwidth
+    )]
+    PrefixLengthOutOfRange {
+        field: String,
+        prefix_len: i32,
+        bitwidth: i32,
+    },
+    #[error("range of '{}' has a low bound greater than its high bound", field)]
+    InvalidRange { field: String },
+    #[error(
+        "table '{}' has ternary, range or optional match fields and requires a positive priority",
+        table
+    )]
+    PriorityRequired { table: String },
+    #[error(
+        "value of '{}' has no translation to the P4Runtime type {}",
+        name,
+        type_name
+    )]
+    Untranslatable { name: String, type_name: String },
+}
+
 #[derive(Error, Debug)]
 pub enum InternalError {
     #[error("Device not found, which should not happen.")]
@@ -62,6 +138,8 @@ pub enum MyError {
     Service(#[from] ServiceError),
     #[error(transparent)]
     Device(#[from] DeviceError),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
 }
 
 pub type Result<T> = std::result::Result<T, MyError>;
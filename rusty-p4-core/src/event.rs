@@ -2,10 +2,12 @@ use crate::entity::{ProtoEntity, UpdateType};
 use crate::p4rt::pipeconf::{DefaultPipeconf, PipeconfID};
 use crate::proto::p4runtime::PacketIn;
 use crate::representation::DeviceID;
+use crate::util::flow::Flow;
 use bytes::{Bytes, BytesMut};
 use rusty_p4_proto::proto::v1::{
     Entity, ForwardingPipelineConfig, MasterArbitrationUpdate, PacketMetadata, Uint128, Update,
 };
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 
@@ -14,4 +16,26 @@ pub struct PacketReceived {
     pub packet: bytes::Bytes,
     pub from: DeviceID,
     pub metadata: Vec<PacketMetadata>,
+    /// the packet-in metadata, keyed by the field name in the `packet_in` header.
+    pub named_metadata: HashMap<String, Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DigestReceived {
+    pub from: DeviceID,
+    pub digest_id: u32,
+    pub name: String,
+    pub list_id: u64,
+    pub timestamp: i64,
+    /// digest messages, each one maps the member name to its value.
+    pub data: Vec<HashMap<String, Bytes>>,
+}
+
+/// An entry with idle timeout was not hit for its idle timeout.
+#[derive(Debug, Clone)]
+pub struct FlowIdleTimeout {
+    pub device: DeviceID,
+    /// the expired entry, only the match fields and metadata are reported by device.
+    pub flow: Flow,
+    pub timestamp: i64,
 }
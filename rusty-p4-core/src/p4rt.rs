@@ -1,4 +1,7 @@
+pub mod api_version;
+pub mod batch;
 pub mod bmv2;
+pub mod p4info_index;
 pub mod pipeconf;
 pub mod pure;
 // pub mod stratum_bmv2;
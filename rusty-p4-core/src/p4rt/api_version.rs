@@ -0,0 +1,63 @@
+/// A P4Runtime API version reported by the Capabilities RPC.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ApiVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ApiVersion {
+    /// The first version with the Capabilities RPC and the optional match kind.
+    pub const V1_1_0: ApiVersion = ApiVersion::new(1, 1, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> ApiVersion {
+        ApiVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl std::str::FromStr for ApiVersion {
+    type Err = ();
+
+    /// Parse a version like "1.2.0", ignoring any pre-release suffix such as "-dev".
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let core = s
+            .trim()
+            .split(|c| c == '-' || c == '+')
+            .next()
+            .unwrap_or_default();
+        let mut parts = core.split('.').map(|part| part.parse::<u32>());
+        let major = parts.next().ok_or(())?.map_err(|_| ())?;
+        let minor = parts.next().unwrap_or(Ok(0)).map_err(|_| ())?;
+        let patch = parts.next().unwrap_or(Ok(0)).map_err(|_| ())?;
+        if parts.next().is_some() {
+            return Err(());
+        }
+        Ok(ApiVersion::new(major, minor, patch))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_api_version() {
+        assert_eq!("1.2.0".parse(), Ok(ApiVersion::new(1, 2, 0)));
+        assert_eq!("1.3.0-dev".parse(), Ok(ApiVersion::new(1, 3, 0)));
+        assert_eq!("1.1".parse(), Ok(ApiVersion::V1_1_0));
+        assert_eq!("".parse::<ApiVersion>(), Err(()));
+        assert_eq!("1.x.0".parse::<ApiVersion>(), Err(()));
+        assert!(ApiVersion::new(1, 0, 0) < ApiVersion::V1_1_0);
+        assert_eq!(ApiVersion::new(1, 2, 0).to_string(), "1.2.0");
+    }
+}
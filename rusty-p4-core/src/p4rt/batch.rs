@@ -0,0 +1,234 @@
+use super::api_version::ApiVersion;
+use super::bmv2::Bmv2SwitchConnection;
+use super::pure::{decode_write_errors, table_entry_key, table_entry_to_entity};
+use crate::entity::{ProtoEntity, ToEntity, UpdateType};
+use crate::error::{DeviceError, MyError, UpdateError};
+use crate::representation::DeviceID;
+use crate::util::flow::Flow;
+use rusty_p4_proto::proto::v1::{
+    entity::Entity, field_match::FieldMatchType, DirectCounterEntry, DirectMeterEntry, TableEntry,
+    Update,
+};
+use std::collections::HashSet;
+
+pub use crate::proto::p4runtime::write_request::Atomicity;
+
+/// A batch of flows and entities written to a device in a single p4runtime `WriteRequest`.
+///
+/// Create one with [Bmv2SwitchConnection::batch], add updates, then [WriteBatch::commit] it.
+/// The [Atomicity] decides what the device does when one of the updates fails,
+/// it defaults to [Atomicity::ContinueOnError] which is the p4runtime default.
+///
+/// # Examples
+/// ```ignore
+/// let flows = conn
+///     .batch()
+///     .atomicity(Atomicity::RollbackOnError)
+///     .insert_flow(flow_a)
+///     .insert_flow(flow_b)
+///     .entity(&meter, UpdateType::Modify)
+///     .commit()
+///     .await?;
+/// ```
+pub struct WriteBatch<'a> {
+    conn: &'a mut Bmv2SwitchConnection,
+    items: Vec<(BatchItem, UpdateType)>,
+    atomicity: Atomicity,
+    error: Option<MyError>,
+}
+
+/// An update in a [WriteBatch].
+#[derive(Debug, Clone)]
+pub enum BatchItem {
+    Flow(Flow),
+    Entity(ProtoEntity),
+}
+
+impl<'a> WriteBatch<'a> {
+    pub fn new(conn: &'a mut Bmv2SwitchConnection) -> Self {
+        WriteBatch {
+            conn,
+            items: Vec::new(),
+            atomicity: Atomicity::ContinueOnError,
+            error: None,
+        }
+    }
+
+    pub fn atomicity(mut self, atomicity: Atomicity) -> Self {
+        self.atomicity = atomicity;
+        self
+    }
+
+    pub fn flow(mut self, flow: Flow, update: UpdateType) -> Self {
+        self.items.push((BatchItem::Flow(flow), update));
+        self
+    }
+
+    pub fn insert_flow(self, flow: Flow) -> Self {
+        self.flow(flow, UpdateType::Insert)
+    }
+
+    pub fn modify_flow(self, flow: Flow) -> Self {
+        self.flow(flow, UpdateType::Modify)
+    }
+
+    pub fn delete_flow(self, flow: Flow) -> Self {
+        self.flow(flow, UpdateType::Delete)
+    }
+
+    /// Add an entity such as a meter, multicast group or clone session.
+    /// If the entity cannot be converted with the device pipeconf, [WriteBatch::commit] will fail without writing anything.
+    pub fn entity<E>(mut self, entity: &E, update: UpdateType) -> Self
+    where
+        E: ToEntity,
+    {
+        if self.error.is_some() {
+            return self;
+        }
+        let conn = &*self.conn;
+        let entity = conn.get_pipeconf().and_then(|pipeconf| {
+            let entity = entity.to_proto_entity(pipeconf.as_ref())?;
+            // direct counters and meters are keyed by the match of their table entry.
+            match &entity.entity {
+                Some(Entity::TableEntry(table_entry)) => require_match_kinds(conn, table_entry)?,
+                Some(Entity::DirectCounterEntry(DirectCounterEntry {
+                    table_entry: Some(table_entry),
+                    ..
+                }))
+                | Some(Entity::DirectMeterEntry(DirectMeterEntry {
+                    table_entry: Some(table_entry),
+                    ..
+                })) => require_match_kinds(conn, table_entry)?,
+                _ => {}
+            }
+            Ok(entity)
+        });
+        match entity {
+            Ok(entity) => self.items.push((BatchItem::Entity(entity), update)),
+            Err(e) => self.error = Some(e),
+        }
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Send all updates in one request.
+    /// Returns the flows in the batch, with their metadata set like [Bmv2SwitchConnection::set_flow].
+    pub async fn commit(self) -> crate::error::Result<Vec<Flow>> {
+        let WriteBatch {
+            conn,
+            items,
+            atomicity,
+            error,
+        } = self;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        let pipeconf = conn.get_pipeconf()?;
+        let mut updates = Vec::with_capacity(items.len());
+        let mut sent = Vec::with_capacity(items.len());
+        let mut flows = vec![];
+        let mut flow_updates = vec![];
+        for (item, update_type) in items {
+            let entity = match item {
+                BatchItem::Flow(mut flow) => {
+                    // the metadata is derived from the flow, so a replayed flow keeps its metadata.
+                    flow.metadata = 0;
+                    let hash = crate::util::hash(&flow);
+                    let table_entry = flow.to_table_entry(pipeconf.as_ref(), hash)?;
+                    require_match_kinds(conn, &table_entry)?;
+                    flow.metadata = hash;
+                    flows.push(flow.clone());
+                    let key = table_entry_key(&table_entry);
+                    flow_updates.push((updates.len(), key, flow.clone(), update_type));
+                    sent.push(BatchItem::Flow(flow));
+                    table_entry_to_entity(table_entry)
+                }
+                BatchItem::Entity(entity) => {
+                    sent.push(BatchItem::Entity(entity.clone()));
+                    entity
+                }
+            };
+            let update_type: crate::proto::p4runtime::update::Type = update_type.into();
+            updates.push(Update {
+                r#type: update_type as i32,
+                entity: Some(entity),
+            });
+        }
+        if updates.is_empty() {
+            return Ok(flows);
+        }
+
+        if let Err(error) = conn.write(updates, atomicity, sent).await {
+            // with ContinueOnError, the updates without error are applied.
+            if let (
+                Atomicity::ContinueOnError,
+                MyError::Device(DeviceError::DeviceWriteError { errors, .. }),
+            ) = (atomicity, &error)
+            {
+                let failed: HashSet<usize> = errors.iter().map(|e| e.index).collect();
+                for (index, key, flow, update_type) in flow_updates {
+                    if !failed.contains(&index) {
+                        conn.record_flow(key, &flow, update_type);
+                    }
+                }
+            }
+            return Err(error);
+        }
+        for (_, key, flow, update_type) in flow_updates {
+            conn.record_flow(key, &flow, update_type);
+        }
+
+        Ok(flows)
+    }
+}
+
+/// Check that the device supports the match kinds of a table entry.
+fn require_match_kinds(
+    conn: &Bmv2SwitchConnection,
+    table_entry: &TableEntry,
+) -> crate::error::Result<()> {
+    if table_entry
+        .r#match
+        .iter()
+        .any(|m| matches!(m.field_match_type, Some(FieldMatchType::Optional(_))))
+    {
+        conn.require_api_version("optional match kind", ApiVersion::V1_1_0)?;
+    }
+    Ok(())
+}
+
+/// Convert the status of a failed write into an error.
+/// If the device reported per-update errors, they are mapped back to the `items` the updates were built from,
+/// and only the failed updates are kept.
+pub(crate) fn write_error(
+    device: DeviceID,
+    error: tonic::Status,
+    items: Vec<BatchItem>,
+) -> MyError {
+    let errors = match decode_write_errors(&error) {
+        Some(errors) => errors,
+        None => return DeviceError::DeviceGrpcError { device, error }.into(),
+    };
+    let mut items: Vec<Option<BatchItem>> = items.into_iter().map(Some).collect();
+    let errors = errors
+        .into_iter()
+        .enumerate()
+        .filter(|(_, e)| e.canonical_code != tonic::Code::Ok as i32)
+        .map(|(index, e)| UpdateError {
+            index,
+            item: items.get_mut(index).and_then(Option::take),
+            code: tonic::Code::from_i32(e.canonical_code),
+            message: e.message,
+            space: e.space,
+            space_code: e.code,
+        })
+        .collect();
+    DeviceError::DeviceWriteError { device, errors }.into()
+}
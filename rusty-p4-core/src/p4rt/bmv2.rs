@@ -1,23 +1,40 @@
 use super::{
+    api_version::ApiVersion,
+    batch::{write_error, BatchItem, WriteBatch},
     pipeconf::Pipeconf,
-    pure::{new_set_entity_request, table_entry_to_entity},
+    pure::{
+        build_packet_out_metadata, decode_digest_data, decode_p4data_bitstring,
+        decode_packet_in_metadata, get_meter_unit, new_digest_ack_request, new_write_request,
+        table_entry_to_entity,
+    },
 };
-use crate::proto::p4config::P4Info;
+use crate::error::InternalError;
+use crate::proto::p4config::{meter_spec, P4Info};
 use crate::proto::p4runtime::{
-    stream_message_request, stream_message_response, PacketMetadata, StreamMessageRequest,
-    StreamMessageResponse, TableEntry,
+    set_forwarding_pipeline_config_request, stream_message_request, stream_message_response,
+    CapabilitiesRequest, PacketMetadata, StreamMessageRequest, StreamMessageResponse, TableEntry,
 };
 use crate::{
-    entity::UpdateType,
-    event::PacketReceived,
+    entity::{
+        counter::{Counter, CounterValue},
+        direct_counter::DirectCounter,
+        direct_meter::DirectMeter,
+        meter::Meter,
+        register::Register,
+        ToEntity, UpdateType,
+    },
+    event::{DigestReceived, FlowIdleTimeout, PacketReceived},
     representation::{ConnectPoint, DeviceID},
     util::{
         flow::Flow,
         publisher::{Handler, Publisher},
+        value::{Decode, Encode},
     },
 };
-use crate::{error::DeviceError, p4rt::pipeconf::DefaultPipeconf};
-use crate::{error::InternalError, p4rt::pure::adjust_value};
+use crate::{
+    error::{DeviceError, MyError, ValidationError},
+    p4rt::pipeconf::DefaultPipeconf,
+};
 use async_trait::async_trait;
 use byteorder::BigEndian;
 use byteorder::ByteOrder;
@@ -25,13 +42,15 @@ use bytes::Bytes;
 use crossbeam::atomic::AtomicCell;
 use dashmap::DashMap;
 use futures::{
-    future::BoxFuture, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt, TryStreamExt,
+    future::BoxFuture, stream::BoxStream, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt,
+    TryStreamExt,
 };
-use log::{debug, error};
+use log::{debug, error, info, warn};
 use parking_lot::RwLock;
 use prost::Message;
 use rusty_p4_proto::proto::v1::{
-    Entity, ForwardingPipelineConfig, MasterArbitrationUpdate, Uint128, Update,
+    CounterData, Entity, ForwardingPipelineConfig, MasterArbitrationUpdate, MeterConfig, P4Data,
+    Role, Uint128, Update,
 };
 use std::io::Read;
 use std::net::SocketAddr;
@@ -58,14 +77,48 @@ pub struct Bmv2Manager {
     connections: Arc<RwLock<HashMap<DeviceID, Bmv2SwitchConnection>>>,
     publisher: Arc<crate::util::publisher::Publisher<Bmv2Event>>,
     packet_publisher: Arc<crate::util::publisher::Publisher<PacketReceived>>,
+    digest_publisher: Arc<crate::util::publisher::Publisher<DigestReceived>>,
+    flow_idle_timeout_publisher: Arc<crate::util::publisher::Publisher<FlowIdleTimeout>>,
+    device_states: Arc<DashMap<DeviceID, DeviceState>>,
     finish_signal_sender:
         Arc<crossbeam::atomic::AtomicCell<Option<tokio::sync::oneshot::Sender<()>>>>,
     finish_signal: futures::future::Shared<crate::util::FinishSignal>,
 }
 
-#[derive(Clone)]
+/// Max number of updates in one `WriteRequest` of [Bmv2SwitchConnection::replay_flows].
+pub const REPLAY_BATCH_SIZE: usize = 1000;
+
+#[derive(Clone, Debug)]
 pub enum Bmv2Event {
+    /// the device is ready for the first time, with its pipeline config pushed.
     DeviceAdded(DeviceID),
+    /// the device is ready again after a reconnect or a takeover from another controller,
+    /// with the flows written before kept by the device or replayed, so they must not be inserted again.
+    DeviceReconnected(DeviceID),
+    DeviceStateChanged(DeviceID, DeviceState),
+}
+
+/// The state of a device managed by [Bmv2Manager].
+///
+/// A device goes `Connecting -> Connected -> Master/Backup -> PipelineSet`.
+/// When its stream is closed it becomes `Disconnected`, then goes through `Connecting` again
+/// if a [ReconnectPolicy] is set, or becomes `Removed`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeviceState {
+    /// connecting to the device and opening the stream.
+    Connecting,
+    /// stream is opened, waiting for the arbitration result.
+    /// A connection without election id stays in this state, as it is neither master nor backup.
+    Connected,
+    /// we are the master of the device.
+    Master,
+    /// another controller is the master of the device.
+    Backup,
+    /// we are the master and the pipeline config is pushed, the device is ready to use.
+    PipelineSet,
+    Disconnected,
+    /// the device is removed from [Bmv2Manager], this is the last state of a device.
+    Removed,
 }
 
 #[async_trait]
@@ -86,6 +139,9 @@ impl crate::app::App for Bmv2Manager {
             connections: Default::default(),
             publisher: Arc::new(Publisher::default()),
             packet_publisher: Default::default(),
+            digest_publisher: Default::default(),
+            flow_idle_timeout_publisher: Default::default(),
+            device_states: Default::default(),
             finish_signal_sender: Arc::new(AtomicCell::new(Some(finish_signal_sender))),
             finish_signal: crate::util::FinishSignal::new(finish_signal).shared(),
         }
@@ -115,6 +171,31 @@ impl Bmv2Manager {
         self.packet_publisher.add_handler(handler);
     }
 
+    pub fn subscribe_digest<T>(&self, handler: T)
+    where
+        T: Handler<DigestReceived>,
+    {
+        self.digest_publisher.add_handler(handler);
+    }
+
+    pub fn subscribe_flow_idle_timeout<T>(&self, handler: T)
+    where
+        T: Handler<FlowIdleTimeout>,
+    {
+        self.flow_idle_timeout_publisher.add_handler(handler);
+    }
+
+    /// Ack a digest list received from device.
+    /// Only needed if the device is added with [Bmv2ConnectionOption::auto_ack_digest] disabled.
+    pub async fn ack_digest(&self, digest: &DigestReceived) -> crate::error::Result<()> {
+        let mut device =
+            self.get_device(digest.from)
+                .ok_or(crate::error::DeviceError::DeviceNotConnected {
+                    device: digest.from,
+                })?;
+        device.ack_digest(digest.digest_id, digest.list_id).await
+    }
+
     fn signal_finish(&self) {
         if let Some(sender) = self.finish_signal_sender.swap(None) {
             sender.send(());
@@ -122,13 +203,64 @@ impl Bmv2Manager {
     }
 
     pub async fn del_device(&self, device: DeviceID) {
-        let mut conns = self.connections.write();
-        conns.remove(&device);
-        if conns.is_empty() {
+        let is_empty = {
+            let mut conns = self.connections.write();
+            conns.remove(&device);
+            conns.is_empty()
+        };
+        self.remove_device_state(device).await;
+        if is_empty {
             self.signal_finish();
         }
     }
 
+    fn has_device(&self, device: DeviceID) -> bool {
+        self.connections.read().contains_key(&device)
+    }
+
+    /// Get the current state of a device, `None` if the device is unknown or removed.
+    pub fn get_device_state(&self, device: DeviceID) -> Option<DeviceState> {
+        self.device_states.get(&device).map(|state| *state)
+    }
+
+    async fn set_device_state(&self, device: DeviceID, state: DeviceState) {
+        let old = self.device_states.insert(device, state);
+        if old != Some(state) {
+            debug!(target: "core", "device {:?} state {:?} -> {:?}", device, old, state);
+            self.publisher
+                .emit(Bmv2Event::DeviceStateChanged(device, state))
+                .await;
+        }
+    }
+
+    async fn remove_device_state(&self, device: DeviceID) {
+        if self.device_states.remove(&device).is_some() {
+            self.publisher
+                .emit(Bmv2Event::DeviceStateChanged(device, DeviceState::Removed))
+                .await;
+        }
+    }
+
+    /// Connect to device, open the stream and take its receiver.
+    async fn open_device(
+        name: &str,
+        address: &str,
+        option: &Bmv2ConnectionOption,
+    ) -> crate::error::Result<(
+        Bmv2SwitchConnection,
+        Sender<StreamMessageRequest>,
+        tonic::Streaming<StreamMessageResponse>,
+    )> {
+        let mut device = Bmv2SwitchConnection::new(name, address, option.clone()).await?;
+        let sender = device.open_stream().await?;
+        let stream = device
+            .take_channel_receiver()
+            .ok_or_else(|| InternalError::Other {
+                err: "take channel receiver failed".to_owned(),
+            })?;
+        Ok((device, sender, stream))
+    }
+
     pub async fn add_device<T>(
         &self,
         name: &str,
@@ -139,53 +271,153 @@ impl Bmv2Manager {
     where
         T: Pipeconf + 'static,
     {
-        let mut device = Bmv2SwitchConnection::new(name, address, option).await?;
+        let auto_ack_digest = option.auto_ack_digest;
+        let delete_idle_flows = option.delete_idle_flows;
         let pipeconf = Arc::new(pipeconf);
-        let id = device.inner_id;
-        let mut sender = device.open_stream().await?;
-        let mut stream = device
-            .take_channel_receiver()
-            .ok_or_else(|| InternalError::Other {
-                err: "take channel receiver failed".to_owned(),
-            })?;
+        let id = Bmv2SwitchConnection::get_inner_id(name, &option);
+        self.set_device_state(id, DeviceState::Connecting).await;
+        let (device, mut sender, mut stream) = match Self::open_device(name, address, &option).await
+        {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.remove_device_state(id).await;
+                return Err(e);
+            }
+        };
         self.connections.write().insert(id, device);
+        self.set_device_state(id, DeviceState::Connected).await;
         let manager = self.clone();
         tokio::spawn(async move {
-            while let Some(Ok(r)) = stream.next().await {
-                if let Some(update) = r.update {
-                    match update {
-                        stream_message_response::Update::Arbitration(masterUpdate) => {
-                            manager
-                                .device_master_updated(id, pipeconf.clone(), masterUpdate)
-                                .await
-                                .unwrap();
-                        }
-                        stream_message_response::Update::Packet(packet) => {
-                            let x = PacketReceived {
-                                packet: packet.payload,
-                                from: id,
-                                metadata: packet.metadata,
-                            };
-                            manager.packet_publisher.emit(x).await;
-                        }
-                        stream_message_response::Update::Digest(p) => {
-                            debug!(target: "core", "StreamMessageResponse: {:#?}", p);
-                        }
-                        stream_message_response::Update::IdleTimeoutNotification(n) => {
-                            debug!(target: "core", "StreamMessageResponse: {:#?}", n);
-                        }
-                        stream_message_response::Update::Other(what) => {
-                            debug!(target: "core", "StreamMessageResponse: {:#?}", what);
-                        }
-                        stream_message_response::Update::Error(err) => {
-                            debug!(target: "core", "StreamMessageResponse: {:#?}", err);
+            loop {
+                while let Some(Ok(r)) = stream.next().await {
+                    if let Some(update) = r.update {
+                        match update {
+                            stream_message_response::Update::Arbitration(masterUpdate) => {
+                                if let Err(e) = manager
+                                    .device_master_updated(id, pipeconf.clone(), masterUpdate)
+                                    .await
+                                {
+                                    error!(target: "core", "device {:?} setup failed: {}", id, e);
+                                }
+                            }
+                            stream_message_response::Update::Packet(packet) => {
+                                let named_metadata =
+                                    decode_packet_in_metadata(pipeconf.as_ref(), &packet.metadata);
+                                let x = PacketReceived {
+                                    packet: packet.payload,
+                                    from: id,
+                                    metadata: packet.metadata,
+                                    named_metadata,
+                                };
+                                manager.packet_publisher.emit(x).await;
+                            }
+                            stream_message_response::Update::Digest(digest_list) => {
+                                if auto_ack_digest {
+                                    let ack = new_digest_ack_request(
+                                        digest_list.digest_id,
+                                        digest_list.list_id,
+                                    );
+                                    if let Err(e) = sender.send(ack).await {
+                                        error!(target: "core", "send digest ack failed: {}", e);
+                                    }
+                                }
+                                let p4info = pipeconf.get_p4info();
+                                let digest = match pipeconf
+                                    .get_p4info_index()
+                                    .digest_by_id(digest_list.digest_id)
+                                {
+                                    Some(digest) => digest,
+                                    None => {
+                                        error!(target: "core", "digest id {} not found in P4Info", digest_list.digest_id);
+                                        continue;
+                                    }
+                                };
+                                let x = DigestReceived {
+                                    from: id,
+                                    digest_id: digest_list.digest_id,
+                                    name: digest.preamble.as_ref().unwrap().name.clone(),
+                                    list_id: digest_list.list_id,
+                                    timestamp: digest_list.timestamp,
+                                    data: digest_list
+                                        .data
+                                        .iter()
+                                        .map(|data| decode_digest_data(p4info, digest, data))
+                                        .collect(),
+                                };
+                                manager.digest_publisher.emit(x).await;
+                            }
+                            stream_message_response::Update::IdleTimeoutNotification(
+                                notification,
+                            ) => {
+                                let flows: Vec<Flow> = notification
+                                    .table_entry
+                                    .iter()
+                                    .filter_map(|entry| {
+                                        match Flow::from_table_entry(pipeconf.as_ref(), entry) {
+                                            Ok(flow) => Some(flow),
+                                            Err(e) => {
+                                                error!(target: "core", "unknown idle timeout entry {:?}: {}", entry, e);
+                                                None
+                                            }
+                                        }
+                                    })
+                                    .collect();
+                                if delete_idle_flows && !flows.is_empty() {
+                                    if let Some(mut device) = manager.get_device(id) {
+                                        let batch = flows
+                                            .iter()
+                                            .cloned()
+                                            .fold(device.batch(), |batch, flow| {
+                                                batch.delete_flow(flow)
+                                            });
+                                        if let Err(e) = batch.commit().await {
+                                            error!(target: "core", "delete idle flows failed: {}", e);
+                                        }
+                                    }
+                                }
+                                for flow in flows {
+                                    let x = FlowIdleTimeout {
+                                        device: id,
+                                        flow,
+                                        timestamp: notification.timestamp,
+                                    };
+                                    manager.flow_idle_timeout_publisher.emit(x).await;
+                                }
+                            }
+                            stream_message_response::Update::Other(what) => {
+                                debug!(target: "core", "StreamMessageResponse: {:#?}", what);
+                            }
+                            stream_message_response::Update::Error(err) => {
+                                debug!(target: "core", "StreamMessageResponse: {:#?}", err);
+                            }
                         }
                     }
                 }
+
+                if !manager.has_device(id) {
+                    // removed by `del_device` while the stream was open, which cleaned up already.
+                    return;
+                }
+                manager
+                    .set_device_state(id, DeviceState::Disconnected)
+                    .await;
+                let policy = match option.reconnect.as_ref() {
+                    Some(policy) => policy,
+                    None => break,
+                };
+                match manager.reconnect(id, &option, policy).await {
+                    Some((new_sender, new_stream)) => {
+                        sender = new_sender;
+                        stream = new_stream;
+                    }
+                    None => break,
+                }
             }
 
-            // clean up
-            manager.del_device(id).await;
+            // clean up, unless the device was removed while reconnecting.
+            if manager.has_device(id) {
+                manager.del_device(id).await;
+            }
         });
 
         Ok(())
@@ -197,42 +429,186 @@ impl Bmv2Manager {
         pipeconf: Arc<dyn Pipeconf>,
         update: MasterArbitrationUpdate,
     ) -> crate::error::Result<()> {
-        let mut conns = self.connections.write();
-        let mut device = conns
-            .get_mut(&device_id)
-            .ok_or(InternalError::DeviceNotFound)?;
-        if device.set_master(update).is_ok() {
-            device.set_forwarding_pipeline_config(pipeconf).await?;
+        let (mut device, is_master, is_elected, was_backup, has_pipeline) = {
+            let mut conns = self.connections.write();
+            let device = conns
+                .get_mut(&device_id)
+                .ok_or(InternalError::DeviceNotFound)?;
+            let was_backup = match device.master_status() {
+                Bmv2MasterStatus::NotMaster { .. } => true,
+                _ => false,
+            };
+            device.set_master(update)?;
+            let is_master = device.get_master().is_ok();
+            let is_elected = device.master_status().election_id().is_some();
+            (
+                device.get_handle(),
+                is_master,
+                is_elected,
+                was_backup,
+                device.pipeconf.is_some(),
+            )
+        };
+        // push the pipeline config once the connection becomes master,
+        // not when the mastership comes back after another controller took it over.
+        // the lock is released before pushing, so it is done on a handle and the pipeconf is stored back after.
+        let pipeline_set = if is_master && !has_pipeline {
+            // a backup taking over keeps the flows written by the previous master.
+            let reconcile = was_backup || device.reconcile_pipeline;
+            let result = device
+                .set_forwarding_pipeline_config_with(pipeconf, reconcile)
+                .await;
+            if result.is_ok() {
+                if let Some(conn) = self.connections.write().get_mut(&device_id) {
+                    conn.pipeconf = device.pipeconf.clone();
+                }
+            }
+            Some(result)
+        } else {
+            None
+        };
+
+        let state = if is_master {
+            DeviceState::Master
+        } else if is_elected {
+            DeviceState::Backup
+        } else {
+            DeviceState::Connected
+        };
+        self.set_device_state(device_id, state).await;
+        if let Some(pipeline_set) = pipeline_set {
+            // committing pipeline config clears the device, write back the flows we installed before.
+            if pipeline_set? == PipelineConfigUpdate::Committed {
+                if let Err(e) = device.replay_flows().await {
+                    error!(target: "core", "replay flows to device {:?} failed: {}", device_id, e);
+                }
+            }
+            self.set_device_state(device_id, DeviceState::PipelineSet)
+                .await;
+            let event = if device.reconnected || was_backup {
+                Bmv2Event::DeviceReconnected(device_id)
+            } else {
+                Bmv2Event::DeviceAdded(device_id)
+            };
+            self.publisher.emit(event).await;
+        } else if is_master && has_pipeline {
+            self.set_device_state(device_id, DeviceState::PipelineSet)
+                .await;
         }
-        drop(conns);
 
-        self.publisher.emit(Bmv2Event::DeviceAdded(device_id)).await;
         Ok(())
     }
 
+    /// Connect to the device again after its stream is closed, following the [ReconnectPolicy].
+    /// Returns `None` if all attempts failed or the device is removed in the meantime.
+    async fn reconnect(
+        &self,
+        id: DeviceID,
+        option: &Bmv2ConnectionOption,
+        policy: &ReconnectPolicy,
+    ) -> Option<(
+        Sender<StreamMessageRequest>,
+        tonic::Streaming<StreamMessageResponse>,
+    )> {
+        let mut backoff = policy.backoff;
+        let mut attempts = 0;
+        loop {
+            if policy.max_attempts.map_or(false, |max| attempts >= max) {
+                error!(target: "core", "device {:?} reconnect failed after {} attempts", id, attempts);
+                return None;
+            }
+            attempts += 1;
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, policy.max_backoff);
+
+            let (name, address, election_id) = match self.connections.read().get(&id) {
+                Some(conn) => (
+                    conn.name.clone(),
+                    conn.address.clone(),
+                    conn.master_status().election_id(),
+                ),
+                None => return None,
+            };
+            // keep the election id raised by `update_master`, not the one the device was added with.
+            let option = Bmv2ConnectionOption {
+                master_update: election_id.map(|(election_id_low, election_id_high)| {
+                    Bmv2MasterUpdateOption {
+                        election_id_high,
+                        election_id_low,
+                    }
+                }),
+                ..option.clone()
+            };
+            info!(target: "core", "reconnecting device {:?}, attempt {}", id, attempts);
+            self.set_device_state(id, DeviceState::Connecting).await;
+            match self.try_reconnect(id, &name, &address, &option).await {
+                Ok(channel) => {
+                    self.set_device_state(id, DeviceState::Connected).await;
+                    return Some(channel);
+                }
+                Err(e) => {
+                    error!(target: "core", "reconnect device {:?} failed: {}", id, e);
+                    self.set_device_state(id, DeviceState::Disconnected).await;
+                }
+            }
+        }
+    }
+
+    async fn try_reconnect(
+        &self,
+        id: DeviceID,
+        name: &str,
+        address: &str,
+        option: &Bmv2ConnectionOption,
+    ) -> crate::error::Result<(
+        Sender<StreamMessageRequest>,
+        tonic::Streaming<StreamMessageResponse>,
+    )> {
+        let (mut device, sender, stream) = Self::open_device(name, address, option).await?;
+        let mut conns = self.connections.write();
+        let old = conns
+            .get(&id)
+            .ok_or(DeviceError::DeviceNotConnected { device: id })?;
+        // keep the installed flows so they can be replayed once the pipeline config is pushed.
+        device.installed_flows = old.installed_flows.clone();
+        device.reconnected = old.reconnected || old.pipeconf.is_some();
+        conns.insert(id, device);
+        Ok((sender, stream))
+    }
+
     pub fn get_device(&self, device: DeviceID) -> Option<Bmv2SwitchConnection> {
         Some(self.connections.read().get(&device)?.get_handle())
     }
 
+    /// The ingress port of a packet-in, which can be of any width up to 32 bits.
     pub fn get_packet_connectpoint(&self, packet: &PacketReceived) -> Option<ConnectPoint> {
-        self.connections
-            .read()
-            .get(&packet.from)
-            .and_then(|conn| {
-                conn.pipeconf.as_ref().and_then(|pipeconf| {
-                    packet
-                        .metadata
-                        .iter()
-                        .find(|x| x.metadata_id == pipeconf.get_packetin_ingress_id())
-                        .map(|x| BigEndian::read_u16(x.value.as_ref()))
-                })
+        packet
+            .named_metadata
+            .get("ingress_port")
+            .filter(|value| value.len() <= 4)
+            .map(|value| {
+                value
+                    .iter()
+                    .fold(0u32, |port, byte| (port << 8) | *byte as u32)
             })
             .map(|port| ConnectPoint {
                 device: packet.from,
-                port: port as u32,
+                port,
             })
     }
 
+    /// Send a new election id to device, see [Bmv2SwitchConnection::update_master].
+    pub async fn update_master(
+        &self,
+        device: DeviceID,
+        option: Bmv2MasterUpdateOption,
+    ) -> crate::error::Result<()> {
+        let mut device = self
+            .get_device(device)
+            .ok_or(crate::error::DeviceError::DeviceNotConnected { device })?;
+        device.update_master(option).await
+    }
+
     pub async fn send_packet(
         &self,
         cp: ConnectPoint,
@@ -269,10 +645,20 @@ pub struct Bmv2SwitchConnection {
     pub client: P4RuntimeClient,
     pub stream_status: Bmv2StreamStatus,
     pipeconf: Option<Arc<dyn Pipeconf>>,
-    pub master_status: Bmv2MasterStatus,
+    /// shared with handles, so they see mastership changes pushed by the device.
+    master_status: Arc<AtomicCell<Bmv2MasterStatus>>,
+    role: Option<Role>,
+    reconcile_pipeline: bool,
+    /// the P4Runtime API version reported by the device, `None` if it does not implement Capabilities.
+    api_version: Option<ApiVersion>,
     is_handle: bool,
+    /// flows written through this connection and its handles, keyed by table, matches and priority.
+    installed_flows: Arc<DashMap<u64, Flow>>,
+    /// the device was ready before this connection was opened by [Bmv2Manager::reconnect].
+    reconnected: bool,
 }
 
+#[derive(Clone)]
 pub struct Bmv2ConnectionOption {
     /// the device id used in p4runtime
     pub p4_device_id: u64,
@@ -281,6 +667,17 @@ pub struct Bmv2ConnectionOption {
     pub inner_device_id: Option<u64>,
     /// the p4runtime election id
     pub master_update: Option<Bmv2MasterUpdateOption>,
+    /// the p4runtime role, `None` means the default role which has full access to the device.
+    pub role: Option<Role>,
+    /// push pipeline config with `RECONCILE_AND_COMMIT` instead of `VERIFY_AND_COMMIT`,
+    /// which keeps the forwarding state if the device supports it.
+    pub reconcile_pipeline: bool,
+    /// ack every digest list once received, otherwise use [Bmv2Manager::ack_digest].
+    pub auto_ack_digest: bool,
+    /// delete the entries reported in idle timeout notifications from device.
+    pub delete_idle_flows: bool,
+    /// reconnect when the stream to device is closed, otherwise the device is removed.
+    pub reconnect: Option<ReconnectPolicy>,
 }
 
 impl Default for Bmv2ConnectionOption {
@@ -289,6 +686,34 @@ impl Default for Bmv2ConnectionOption {
             p4_device_id: 1,
             inner_device_id: None,
             master_update: Some(Bmv2MasterUpdateOption::default()),
+            role: None,
+            reconcile_pipeline: false,
+            auto_ack_digest: true,
+            delete_idle_flows: false,
+            reconnect: None,
+        }
+    }
+}
+
+/// How [Bmv2Manager] reconnects to a device whose stream is closed, e.g. because the switch restarted.
+///
+/// After reconnected, the arbitration is sent again, the pipeline config is pushed
+/// and the flows installed before are written back.
+#[derive(Copy, Clone, Debug)]
+pub struct ReconnectPolicy {
+    /// delay before the first attempt, doubled after each failed attempt.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// give up and remove the device after this many failed attempts, `None` means retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            max_attempts: None,
         }
     }
 }
@@ -317,11 +742,7 @@ impl Bmv2SwitchConnection {
         let name = name.to_owned();
         let address = address.to_owned();
 
-        let inner_id = if let Some(inner_id) = options.inner_device_id {
-            inner_id
-        } else {
-            crate::util::hash(&name)
-        };
+        let inner_id = Self::get_inner_id(&name, &options).0;
         let device_id = options.p4_device_id;
 
         let mut client_stub = crate::proto::p4runtime::p4_runtime_client::P4RuntimeClient::connect(
@@ -333,6 +754,25 @@ impl Bmv2SwitchConnection {
             error: e,
         })?;
 
+        let api_version = match client_stub.capabilities(CapabilitiesRequest {}).await {
+            Ok(response) => {
+                let version = &response.get_ref().p4runtime_api_version;
+                let parsed = version.parse().ok();
+                if parsed.is_none() {
+                    warn!(target: "core", "device {} reports unknown P4Runtime version {:?}", name, version);
+                }
+                parsed
+            }
+            Err(status) if status.code() == tonic::Code::Unimplemented => None,
+            Err(status) => {
+                return Err(DeviceError::DeviceGrpcError {
+                    device: DeviceID(inner_id),
+                    error: status,
+                }
+                .into())
+            }
+        };
+
         Ok(Bmv2SwitchConnection {
             name,
             inner_id: DeviceID(inner_id),
@@ -342,10 +782,49 @@ impl Bmv2SwitchConnection {
             stream_status: Bmv2StreamStatus::None,
             pipeconf: None,
             is_handle: false,
-            master_status: Bmv2MasterStatus::from(options.master_update),
+            master_status: Arc::new(AtomicCell::new(Bmv2MasterStatus::from(
+                options.master_update,
+            ))),
+            role: options.role,
+            reconcile_pipeline: options.reconcile_pipeline,
+            api_version,
+            installed_flows: Default::default(),
+            reconnected: false,
         })
     }
 
+    /// The P4Runtime API version reported by the device, `None` if it does not implement Capabilities.
+    pub fn api_version(&self) -> Option<ApiVersion> {
+        self.api_version
+    }
+
+    /// Fail with [DeviceError::Unsupported] unless the device reports at least the `required` API version.
+    pub fn require_api_version(
+        &self,
+        feature: &'static str,
+        required: ApiVersion,
+    ) -> crate::error::Result<()> {
+        match self.api_version {
+            Some(version) if version >= required => Ok(()),
+            version => Err(DeviceError::Unsupported {
+                device: self.inner_id,
+                feature,
+                required,
+                version,
+            }
+            .into()),
+        }
+    }
+
+    /// The [DeviceID] of a device connected with the options.
+    pub fn get_inner_id(name: &str, options: &Bmv2ConnectionOption) -> DeviceID {
+        if let Some(inner_id) = options.inner_device_id {
+            DeviceID(inner_id)
+        } else {
+            DeviceID(crate::util::hash(name))
+        }
+    }
+
     pub async fn open_stream(&mut self) -> crate::error::Result<Sender<StreamMessageRequest>> {
         if self.is_handle {
             return Err(DeviceError::Other {
@@ -357,9 +836,17 @@ impl Bmv2SwitchConnection {
         match self.stream_status {
             Bmv2StreamStatus::None => {
                 let (mut send_stream, receiver) = tokio::sync::mpsc::channel(4096);
+                let election_id =
+                    self.master_status()
+                        .election_id()
+                        .ok_or_else(|| DeviceError::NotMaster {
+                            device: self.inner_id,
+                            reason: "No elect".to_owned(),
+                        })?;
                 let master_up_req = crate::p4rt::pure::new_master_update_request(
                     self.device_id,
-                    self.get_elect().unwrap(),
+                    election_id,
+                    self.role.clone(),
                 );
                 send_stream.send(master_up_req).await.unwrap();
                 let recv_stream = self
@@ -395,11 +882,23 @@ impl Bmv2SwitchConnection {
         &mut self,
         egress_port: u32,
         packet: Bytes,
+    ) -> crate::error::Result<()> {
+        self.packet_out_with_metadata(&[("egress_port", egress_port.encode())], packet)
+            .await
+    }
+
+    /// Send a packet-out with metadata named as the fields of the `packet_out` header in P4Info,
+    /// e.g. `egress_port` or `queue_id`. Each value is encoded to the bitwidth of its field.
+    pub async fn packet_out_with_metadata(
+        &mut self,
+        metadata: &[(&str, Bytes)],
+        packet: Bytes,
     ) -> crate::error::Result<()> {
         let pipeconf = self.pipeconf.as_ref().ok_or_else(|| DeviceError::Other {
             device: self.inner_id,
             error: "pipeconf not set".to_owned(),
         })?;
+        let metadata = build_packet_out_metadata(pipeconf.as_ref(), metadata)?;
         let mut sender = match self.stream_status {
             Bmv2StreamStatus::None => {
                 let (send_stream, receiver) = tokio::sync::mpsc::channel(4096);
@@ -425,19 +924,53 @@ impl Bmv2SwitchConnection {
             Bmv2StreamStatus::Streaming(ref sender) => sender.clone(),
         };
 
-        let request = super::pure::new_packet_out_request(&pipeconf, egress_port, packet);
+        let request = super::pure::new_packet_out_request(metadata, packet);
         sender.send(request).await.unwrap();
         Ok(())
     }
 
+    pub async fn ack_digest(&mut self, digest_id: u32, list_id: u64) -> crate::error::Result<()> {
+        let mut sender = match self.stream_status {
+            Bmv2StreamStatus::StreamOpened { ref sender, .. } => sender.clone(),
+            Bmv2StreamStatus::Streaming(ref sender) => sender.clone(),
+            Bmv2StreamStatus::None => {
+                return Err(DeviceError::Other {
+                    device: self.inner_id,
+                    error: "stream not opened".to_owned(),
+                }
+                .into());
+            }
+        };
+        sender
+            .send(new_digest_ack_request(digest_id, list_id))
+            .await
+            .map_err(|_| DeviceError::DeviceNotConnected {
+                device: self.inner_id,
+            })?;
+        Ok(())
+    }
+
+    /// Set the pipeline config of device, skipped if the cookie of the config on device matches ours.
     pub async fn set_forwarding_pipeline_config(
         &mut self,
         pipeconf: Arc<dyn Pipeconf>,
-    ) -> crate::error::Result<()> {
+    ) -> crate::error::Result<PipelineConfigUpdate> {
+        let reconcile = self.reconcile_pipeline;
+        self.set_forwarding_pipeline_config_with(pipeconf, reconcile)
+            .await
+    }
+
+    /// Same as [Bmv2SwitchConnection::set_forwarding_pipeline_config],
+    /// but `reconcile` chooses RECONCILE_AND_COMMIT, which keeps the forwarding state on device, over VERIFY_AND_COMMIT.
+    pub async fn set_forwarding_pipeline_config_with(
+        &mut self,
+        pipeconf: Arc<dyn Pipeconf>,
+        reconcile: bool,
+    ) -> crate::error::Result<PipelineConfigUpdate> {
         let (e_low, e_high) = self.get_master()?;
         let master_arbitration = MasterArbitrationUpdate {
             device_id: self.device_id,
-            role: None,
+            role: self.role.clone(),
             election_id: Uint128 {
                 high: e_high,
                 low: e_low,
@@ -446,14 +979,39 @@ impl Bmv2SwitchConnection {
             status: None,
         };
         let p4info = pipeconf.get_p4info();
-        let bmv2_json_file_path = pipeconf.get_bmv2_file_path();
+        let device_config = super::pure::read_device_config(pipeconf.get_bmv2_file_path()).await?;
+        let cookie = super::pure::pipeline_config_cookie(p4info, &device_config);
+        match self.get_pipeline_config_cookie().await {
+            Ok(Some(device_cookie)) if device_cookie == cookie => {
+                debug!(target: "core", "device {:?} already has the pipeline config", self.inner_id);
+                self.pipeconf = Some(pipeconf);
+                return Ok(PipelineConfigUpdate::Unchanged);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                // e.g. no pipeline config is set yet.
+                debug!(target: "core", "get pipeline config cookie failed: {}", e);
+            }
+        }
+        let (action, update) = if reconcile {
+            (
+                set_forwarding_pipeline_config_request::Action::ReconcileAndCommit,
+                PipelineConfigUpdate::Reconciled,
+            )
+        } else {
+            (
+                set_forwarding_pipeline_config_request::Action::VerifyAndCommit,
+                PipelineConfigUpdate::Committed,
+            )
+        };
         let request = super::pure::new_set_forwarding_pipeline_config_request(
             p4info,
-            bmv2_json_file_path,
+            device_config,
+            cookie,
             &master_arbitration,
             self.device_id,
-        )
-        .await?;
+            action,
+        );
         self.client
             .set_forwarding_pipeline_config(tonic::Request::new(request))
             .await
@@ -464,44 +1022,527 @@ impl Bmv2SwitchConnection {
 
         self.pipeconf = Some(pipeconf);
 
-        Ok(())
+        Ok(update)
+    }
+
+    /// Get the cookie of the pipeline config on device, `None` if the device does not report one.
+    pub async fn get_pipeline_config_cookie(&mut self) -> crate::error::Result<Option<u64>> {
+        let request = super::pure::new_get_pipeline_config_cookie_request(self.device_id);
+        let response = self
+            .client
+            .get_forwarding_pipeline_config(tonic::Request::new(request))
+            .await
+            .map_err(|e| crate::error::DeviceError::DeviceGrpcError {
+                device: self.inner_id,
+                error: e,
+            })?
+            .into_inner();
+        Ok(response
+            .config
+            .and_then(|config| config.cookie)
+            .map(|cookie| cookie.cookie))
     }
 
     pub async fn write_table_entry(&mut self, table_entry: TableEntry) -> crate::error::Result<()> {
-        let (e_low, e_high) = self.get_master()?;
         let update_type = if table_entry.is_default_action {
             crate::proto::p4runtime::update::Type::Modify
         } else {
             crate::proto::p4runtime::update::Type::Insert
         };
-        let mut request = crate::proto::p4runtime::WriteRequest {
-            device_id: self.device_id,
-            role_id: 0,
-            election_id: Some(Uint128 {
-                high: e_high,
-                low: e_low,
-            }),
-            updates: vec![Update {
-                r#type: update_type as i32,
-                entity: Some(Entity {
-                    entity: Some(crate::proto::p4runtime::entity::Entity::TableEntry(
-                        table_entry.clone(),
-                    )),
-                }),
-            }],
-            atomicity: 0,
-        };
+        let entity = table_entry_to_entity(table_entry);
+        let updates = vec![Update {
+            r#type: update_type as i32,
+            entity: Some(entity.clone()),
+        }];
+        self.write(
+            updates,
+            super::batch::Atomicity::ContinueOnError,
+            vec![BatchItem::Entity(entity)],
+        )
+        .await
+    }
+
+    /// Send updates in one p4runtime `WriteRequest`, every write to device goes through here.
+    /// The device id, role and election id are taken from this connection,
+    /// and nothing is sent if we are not the master of the device.
+    /// `items` are the flows or entities the updates were built from, used to report errors.
+    pub(crate) async fn write(
+        &mut self,
+        updates: Vec<Update>,
+        atomicity: super::batch::Atomicity,
+        items: Vec<BatchItem>,
+    ) -> crate::error::Result<()> {
+        let election_id = self.get_master()?;
+        let role_id = self.role.as_ref().map(|role| role.id).unwrap_or_default();
+        let request = new_write_request(self.device_id, role_id, election_id, updates, atomicity);
         self.client
             .write(tonic::Request::new(request))
             .await
-            .map_err(|error| crate::error::DeviceError::DeviceGrpcError {
-                device: self.inner_id,
-                error,
-            })?;
+            .map_err(|error| write_error(self.inner_id, error, items))?;
 
         Ok(())
     }
 
+    /// Read entities from the device using the p4runtime `Read` RPC.
+    /// The returned stream yields every entity in every `ReadResponse`.
+    pub async fn read_entities(
+        &mut self,
+        entities: Vec<Entity>,
+    ) -> crate::error::Result<BoxStream<'static, crate::error::Result<Entity>>> {
+        let device = self.inner_id;
+        let request = super::pure::new_read_request(self.device_id, entities);
+        let response = self
+            .client
+            .read(tonic::Request::new(request))
+            .await
+            .map_err(|error| DeviceError::DeviceGrpcError { device, error })?
+            .into_inner();
+
+        Ok(response
+            .map_err(move |error| MyError::from(DeviceError::DeviceGrpcError { device, error }))
+            .map_ok(|response| {
+                futures::stream::iter(response.entities.into_iter().map(Ok::<_, MyError>))
+            })
+            .try_flatten()
+            .boxed())
+    }
+
+    /// Read the flows installed in the given table.
+    pub async fn read_flows(
+        &mut self,
+        table: &str,
+    ) -> crate::error::Result<BoxStream<'static, crate::error::Result<Flow>>> {
+        let pipeconf = self.get_pipeconf()?;
+        let table_id = pipeconf.get_p4info_index().table_id(table).ok_or_else(|| {
+            ValidationError::UnknownName {
+                kind: "table",
+                name: table.to_owned(),
+            }
+        })?;
+        self.read_table_entries(pipeconf, table_id).await
+    }
+
+    /// Read the flows installed in all tables.
+    pub async fn read_all_flows(
+        &mut self,
+    ) -> crate::error::Result<BoxStream<'static, crate::error::Result<Flow>>> {
+        let pipeconf = self.get_pipeconf()?;
+        // table id 0 is the wildcard for all tables.
+        self.read_table_entries(pipeconf, 0).await
+    }
+
+    async fn read_table_entries(
+        &mut self,
+        pipeconf: Arc<dyn Pipeconf>,
+        table_id: u32,
+    ) -> crate::error::Result<BoxStream<'static, crate::error::Result<Flow>>> {
+        let entity = table_entry_to_entity(TableEntry {
+            table_id,
+            ..Default::default()
+        });
+        let stream = self.read_entities(vec![entity]).await?;
+
+        Ok(stream
+            .try_filter_map(move |entity| {
+                let result = match entity.entity {
+                    Some(crate::proto::p4runtime::entity::Entity::TableEntry(table_entry)) => {
+                        Flow::from_table_entry(pipeconf.as_ref(), &table_entry)
+                            .map(Some)
+                            .map_err(Into::into)
+                    }
+                    _ => Ok(None),
+                };
+                futures::future::ready(result)
+            })
+            .boxed())
+    }
+
+    /// Read a cell of a register.
+    pub async fn read_register(&mut self, name: &str, index: i64) -> crate::error::Result<P4Data> {
+        self.read_registers(name, Some(index))
+            .await?
+            .pop()
+            .map(|(_, data)| data)
+            .ok_or_else(|| {
+                DeviceError::Other {
+                    device: self.inner_id,
+                    error: format!("register {} index {} not returned", name, index),
+                }
+                .into()
+            })
+    }
+
+    /// Read a cell of a register and decode it, e.g. `read_register_as::<u32>("counts", 1)`.
+    pub async fn read_register_as<T: Decode>(
+        &mut self,
+        name: &str,
+        index: i64,
+    ) -> crate::error::Result<T> {
+        let data = self.read_register(name, index).await?;
+        self.decode_register(name, index, &data)
+    }
+
+    /// Read all cells of a register, with their indexes.
+    pub async fn read_register_array(
+        &mut self,
+        name: &str,
+    ) -> crate::error::Result<Vec<(i64, P4Data)>> {
+        self.read_registers(name, None).await
+    }
+
+    /// Read all cells of a register and decode them, with their indexes.
+    pub async fn read_register_array_as<T: Decode>(
+        &mut self,
+        name: &str,
+    ) -> crate::error::Result<Vec<(i64, T)>> {
+        self.read_registers(name, None)
+            .await?
+            .into_iter()
+            .map(|(index, data)| Ok((index, self.decode_register(name, index, &data)?)))
+            .collect()
+    }
+
+    /// Write a cell of a register.
+    pub async fn write_register(
+        &mut self,
+        name: &str,
+        index: i64,
+        data: P4Data,
+    ) -> crate::error::Result<()> {
+        let register = Register {
+            name: name.to_owned().into(),
+            index: Some(index),
+            data: Some(data),
+        };
+        self.set_entity(&register, UpdateType::Modify).await
+    }
+
+    async fn read_registers(
+        &mut self,
+        name: &str,
+        index: Option<i64>,
+    ) -> crate::error::Result<Vec<(i64, P4Data)>> {
+        let register = Register {
+            name: name.to_owned().into(),
+            index,
+            data: None,
+        };
+        let entity = self.to_entity(&register)?;
+        let entities: Vec<Entity> = self
+            .read_entities(vec![entity])
+            .await?
+            .try_collect()
+            .await?;
+        Ok(entities
+            .into_iter()
+            .filter_map(|entity| match entity.entity {
+                Some(crate::proto::p4runtime::entity::Entity::RegisterEntry(entry)) => Some((
+                    entry.index.map(|i| i.index).unwrap_or_default(),
+                    entry.data.unwrap_or_default(),
+                )),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn decode_register<T: Decode>(
+        &self,
+        name: &str,
+        index: i64,
+        data: &P4Data,
+    ) -> crate::error::Result<T> {
+        decode_p4data_bitstring(data).ok_or_else(|| {
+            DeviceError::Other {
+                device: self.inner_id,
+                error: format!(
+                    "register {} index {} cannot be decoded as {}",
+                    name,
+                    index,
+                    std::any::type_name::<T>()
+                ),
+            }
+            .into()
+        })
+    }
+
+    /// Read a cell of an indirect counter.
+    pub async fn read_counter(
+        &mut self,
+        name: &str,
+        index: i64,
+    ) -> crate::error::Result<CounterValue> {
+        self.read_counters(name, Some(index))
+            .await?
+            .pop()
+            .ok_or_else(|| {
+                DeviceError::Other {
+                    device: self.inner_id,
+                    error: format!("counter {} index {} not returned", name, index),
+                }
+                .into()
+            })
+    }
+
+    /// Read all cells of an indirect counter.
+    pub async fn read_counter_array(
+        &mut self,
+        name: &str,
+    ) -> crate::error::Result<Vec<CounterValue>> {
+        self.read_counters(name, None).await
+    }
+
+    async fn read_counters(
+        &mut self,
+        name: &str,
+        index: Option<i64>,
+    ) -> crate::error::Result<Vec<CounterValue>> {
+        let counter = Counter {
+            name: name.to_owned().into(),
+            index,
+        };
+        let entity = self.to_entity(&counter)?;
+        let entities: Vec<Entity> = self
+            .read_entities(vec![entity])
+            .await?
+            .try_collect()
+            .await?;
+        Ok(entities
+            .into_iter()
+            .filter_map(|entity| match entity.entity {
+                Some(crate::proto::p4runtime::entity::Entity::CounterEntry(entry)) => {
+                    let data = entry.data.unwrap_or_default();
+                    Some(CounterValue {
+                        index: entry.index.map(|i| i.index).unwrap_or_default(),
+                        packets: data.packet_count,
+                        bytes: data.byte_count,
+                    })
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Read the config of a meter cell, `None` if the cell has the default config.
+    pub async fn read_meter(
+        &mut self,
+        name: &str,
+        index: i64,
+    ) -> crate::error::Result<Option<MeterConfig>> {
+        self.read_meters(name, Some(index))
+            .await?
+            .pop()
+            .map(|(_, config)| config)
+            .ok_or_else(|| {
+                DeviceError::Other {
+                    device: self.inner_id,
+                    error: format!("meter {} index {} not returned", name, index),
+                }
+                .into()
+            })
+    }
+
+    /// Read the configs of all cells of a meter, with their indexes.
+    pub async fn read_meter_array(
+        &mut self,
+        name: &str,
+    ) -> crate::error::Result<Vec<(i64, Option<MeterConfig>)>> {
+        self.read_meters(name, None).await
+    }
+
+    /// Set the config of a meter cell, `None` resets the cell to the default config.
+    pub async fn set_meter(
+        &mut self,
+        name: &str,
+        index: i64,
+        config: Option<MeterConfig>,
+    ) -> crate::error::Result<()> {
+        let meter = Meter {
+            name: name.to_owned().into(),
+            index: Some(index),
+            config,
+        };
+        self.set_entity(&meter, UpdateType::Modify).await
+    }
+
+    /// Get the unit of a meter or a direct meter declared in P4Info.
+    pub fn meter_unit(&self, name: &str) -> crate::error::Result<meter_spec::Unit> {
+        let pipeconf = self.get_pipeconf()?;
+        get_meter_unit(pipeconf.get_p4info_index(), name).ok_or_else(|| {
+            ValidationError::UnknownName {
+                kind: "meter",
+                name: name.to_owned(),
+            }
+            .into()
+        })
+    }
+
+    async fn read_meters(
+        &mut self,
+        name: &str,
+        index: Option<i64>,
+    ) -> crate::error::Result<Vec<(i64, Option<MeterConfig>)>> {
+        let meter = Meter {
+            name: name.to_owned().into(),
+            index,
+            config: None,
+        };
+        let entity = self.to_entity(&meter)?;
+        let entities: Vec<Entity> = self
+            .read_entities(vec![entity])
+            .await?
+            .try_collect()
+            .await?;
+        Ok(entities
+            .into_iter()
+            .filter_map(|entity| match entity.entity {
+                Some(crate::proto::p4runtime::entity::Entity::MeterEntry(entry)) => Some((
+                    entry.index.map(|i| i.index).unwrap_or_default(),
+                    entry.config,
+                )),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Read the direct counter of a flow.
+    pub async fn read_direct_counter(&mut self, flow: &Flow) -> crate::error::Result<CounterData> {
+        let counter = DirectCounter {
+            flow: flow.clone(),
+            data: None,
+        };
+        let entity = self.to_entity(&counter)?;
+        self.read_direct_counter_entries(entity)
+            .await?
+            .pop()
+            .map(|(_, data)| data)
+            .ok_or_else(|| self.entry_not_returned(flow))
+    }
+
+    /// Read the direct counters of all entries in a table.
+    pub async fn read_direct_counters(
+        &mut self,
+        table: &str,
+    ) -> crate::error::Result<Vec<(Flow, CounterData)>> {
+        let pipeconf = self.get_pipeconf()?;
+        let table_id = pipeconf.get_p4info_index().table_id(table).ok_or_else(|| {
+            ValidationError::UnknownName {
+                kind: "table",
+                name: table.to_owned(),
+            }
+        })?;
+        let entity = Entity {
+            entity: Some(crate::proto::p4runtime::entity::Entity::DirectCounterEntry(
+                crate::proto::p4runtime::DirectCounterEntry {
+                    table_entry: Some(TableEntry {
+                        table_id,
+                        ..Default::default()
+                    }),
+                    data: None,
+                },
+            )),
+        };
+        self.read_direct_counter_entries(entity).await
+    }
+
+    /// Set the direct counter of a flow, e.g. to reset it to zero.
+    pub async fn write_direct_counter(
+        &mut self,
+        flow: &Flow,
+        data: CounterData,
+    ) -> crate::error::Result<()> {
+        let counter = DirectCounter {
+            flow: flow.clone(),
+            data: Some(data),
+        };
+        self.set_entity(&counter, UpdateType::Modify).await
+    }
+
+    async fn read_direct_counter_entries(
+        &mut self,
+        entity: Entity,
+    ) -> crate::error::Result<Vec<(Flow, CounterData)>> {
+        let pipeconf = self.get_pipeconf()?;
+        let entities: Vec<Entity> = self
+            .read_entities(vec![entity])
+            .await?
+            .try_collect()
+            .await?;
+        let mut counters = Vec::with_capacity(entities.len());
+        for entity in entities {
+            if let Some(crate::proto::p4runtime::entity::Entity::DirectCounterEntry(entry)) =
+                entity.entity
+            {
+                let table_entry = entry.table_entry.unwrap_or_default();
+                let flow = Flow::from_table_entry(pipeconf.as_ref(), &table_entry)?;
+                counters.push((flow, entry.data.unwrap_or_default()));
+            }
+        }
+        Ok(counters)
+    }
+
+    /// Read the direct meter config of a flow, `None` if the meter has the default config.
+    pub async fn read_direct_meter(
+        &mut self,
+        flow: &Flow,
+    ) -> crate::error::Result<Option<MeterConfig>> {
+        let meter = DirectMeter {
+            flow: flow.clone(),
+            config: None,
+        };
+        let entity = self.to_entity(&meter)?;
+        let entities: Vec<Entity> = self
+            .read_entities(vec![entity])
+            .await?
+            .try_collect()
+            .await?;
+        entities
+            .into_iter()
+            .find_map(|entity| match entity.entity {
+                Some(crate::proto::p4runtime::entity::Entity::DirectMeterEntry(entry)) => {
+                    Some(entry.config)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| self.entry_not_returned(flow))
+    }
+
+    /// Set the direct meter config of a flow, `None` resets the meter to the default config.
+    pub async fn set_direct_meter(
+        &mut self,
+        flow: &Flow,
+        config: Option<MeterConfig>,
+    ) -> crate::error::Result<()> {
+        let meter = DirectMeter {
+            flow: flow.clone(),
+            config,
+        };
+        self.set_entity(&meter, UpdateType::Modify).await
+    }
+
+    fn entry_not_returned(&self, flow: &Flow) -> MyError {
+        DeviceError::Other {
+            device: self.inner_id,
+            error: format!("entry of flow {:?} not returned", flow),
+        }
+        .into()
+    }
+
+    /// Convert an entity with the pipeconf.
+    fn to_entity<E: ToEntity>(&self, entity: &E) -> crate::error::Result<Entity> {
+        let pipeconf = self.get_pipeconf()?;
+        Ok(entity.to_proto_entity(pipeconf.as_ref())?)
+    }
+
+    pub fn get_pipeconf(&self) -> crate::error::Result<Arc<dyn Pipeconf>> {
+        self.pipeconf.clone().ok_or_else(|| {
+            DeviceError::Other {
+                device: self.inner_id,
+                error: "pipeconf not set".to_owned(),
+            }
+            .into()
+        })
+    }
+
     pub fn take_channel_receiver(
         &mut self,
     ) -> Option<tonic::Streaming<rusty_p4_proto::proto::v1::StreamMessageResponse>> {
@@ -532,69 +1573,153 @@ impl Bmv2SwitchConnection {
             stream_status: status,
             pipeconf: self.pipeconf.clone(),
             master_status: self.master_status.clone(),
+            role: self.role.clone(),
+            reconcile_pipeline: self.reconcile_pipeline,
+            api_version: self.api_version,
             is_handle: true,
+            installed_flows: self.installed_flows.clone(),
+            reconnected: self.reconnected,
         }
     }
 
-    pub async fn set_flow(
-        &mut self,
-        mut flow: Flow,
-        update: UpdateType,
-    ) -> crate::error::Result<Flow> {
-        let pipeconf = if let Some(pipeconf) = self.pipeconf.as_ref() {
-            pipeconf
-        } else {
-            todo!()
-        };
-        let hash = crate::util::hash(&flow);
-        let table_entry = flow.to_table_entry(&pipeconf, hash);
-        let request = new_set_entity_request(1, table_entry_to_entity(table_entry), update.into());
-        match self.client.write(tonic::Request::new(request)).await {
-            Ok(response) => {
-                debug!(target: "core", "set entity response: {:?}", response);
+    pub async fn set_flow(&mut self, flow: Flow, update: UpdateType) -> crate::error::Result<Flow> {
+        let mut flows = self.batch().flow(flow, update).commit().await?;
+        Ok(flows.remove(0))
+    }
+
+    /// Remember a flow written to device, so it can be replayed by [Bmv2SwitchConnection::replay_flows].
+    /// `key` is the [table_entry_key](super::pure::table_entry_key) of the table entry of the flow.
+    pub(crate) fn record_flow(&self, key: u64, flow: &Flow, update: UpdateType) {
+        match update {
+            UpdateType::Insert | UpdateType::Modify => {
+                self.installed_flows.insert(key, flow.clone());
             }
-            Err(e) => {
-                error!(target: "core", "grpc send error: {:?}", e);
+            UpdateType::Delete => {
+                self.installed_flows.remove(&key);
             }
+            UpdateType::Unspecified => {}
         }
-        flow.metadata = hash;
-        Ok(flow)
+    }
+
+    /// The flows written through this connection and not deleted since.
+    pub fn installed_flows(&self) -> Vec<Flow> {
+        self.installed_flows
+            .iter()
+            .map(|flow| flow.value().clone())
+            .collect()
+    }
+
+    /// Write all [Bmv2SwitchConnection::installed_flows] to the device again, e.g. after the device restarted.
+    /// The flows are sent in requests of at most [REPLAY_BATCH_SIZE] updates, to stay under the gRPC message size limit.
+    pub async fn replay_flows(&mut self) -> crate::error::Result<Vec<Flow>> {
+        let flows = self.installed_flows();
+        if flows.is_empty() {
+            return Ok(flows);
+        }
+        debug!(target: "core", "replay {} flows to device {:?}", flows.len(), self.inner_id);
+        let mut replayed = Vec::with_capacity(flows.len());
+        for chunk in flows.chunks(REPLAY_BATCH_SIZE) {
+            let batch = chunk
+                .iter()
+                .cloned()
+                .fold(self.batch(), |batch, flow| batch.insert_flow(flow));
+            replayed.extend(batch.commit().await?);
+        }
+        Ok(replayed)
     }
 
     pub async fn insert_flow(&mut self, mut flow: Flow) -> crate::error::Result<Flow> {
         self.set_flow(flow, UpdateType::Insert).await
     }
 
+    /// Start a [WriteBatch] to write many flows and entities in one request.
+    pub fn batch(&mut self) -> WriteBatch {
+        WriteBatch::new(self)
+    }
+
+    pub async fn set_entity<E>(
+        &mut self,
+        entity: &E,
+        update: UpdateType,
+    ) -> crate::error::Result<()>
+    where
+        E: ToEntity,
+    {
+        self.batch().entity(entity, update).commit().await?;
+        Ok(())
+    }
+
+    /// Handle an arbitration update from device.
+    /// The device sends one to every controller when the master changes, the status code is `OK`
+    /// only in the one sent to the master.
     pub fn set_master(&mut self, update: MasterArbitrationUpdate) -> crate::error::Result<()> {
-        if let Some(eid) = update.election_id {
-            match self.master_status {
-                Bmv2MasterStatus::NoElect => {}
-                Bmv2MasterStatus::Elect {
-                    election_id_low,
-                    election_id_high,
-                } if election_id_low == eid.low && election_id_high == eid.high => {
-                    self.master_status = Bmv2MasterStatus::Master {
-                        election_id_low,
-                        election_id_high,
-                    }
-                }
-                Bmv2MasterStatus::Elect {
-                    election_id_low,
-                    election_id_high,
-                } => {}
-                Bmv2MasterStatus::Master {
-                    election_id_low,
-                    election_id_high,
-                } => {}
-                Bmv2MasterStatus::NotMaster {} => {}
+        let (election_id_low, election_id_high) = match self.master_status().election_id() {
+            Some(election_id) => election_id,
+            None => return Ok(()),
+        };
+        let is_master = match update.status.as_ref() {
+            Some(status) => status.code == tonic::Code::Ok as i32,
+            // no status, compare the election id of master with ours.
+            None => update.election_id.map_or(false, |eid| {
+                eid.low == election_id_low && eid.high == election_id_high
+            }),
+        };
+        let status = if is_master {
+            Bmv2MasterStatus::Master {
+                election_id_low,
+                election_id_high,
             }
-        }
+        } else {
+            Bmv2MasterStatus::NotMaster {
+                election_id_low,
+                election_id_high,
+            }
+        };
+        debug!(target: "core", "device {:?} master status: {:?}", self.inner_id, status);
+        self.master_status.store(status);
 
         Ok(())
     }
 
+    /// Send an arbitration update with a new election id, e.g. a higher one to take over the device from another controller.
+    /// The result comes back as an arbitration update from device.
+    pub async fn update_master(
+        &mut self,
+        option: Bmv2MasterUpdateOption,
+    ) -> crate::error::Result<()> {
+        let mut sender = match self.stream_status {
+            Bmv2StreamStatus::StreamOpened { ref sender, .. } => sender.clone(),
+            Bmv2StreamStatus::Streaming(ref sender) => sender.clone(),
+            Bmv2StreamStatus::None => {
+                return Err(DeviceError::Other {
+                    device: self.inner_id,
+                    error: "stream not opened".to_owned(),
+                }
+                .into());
+            }
+        };
+        self.master_status
+            .store(Bmv2MasterStatus::from(Some(option)));
+        let request = crate::p4rt::pure::new_master_update_request(
+            self.device_id,
+            (option.election_id_low, option.election_id_high),
+            self.role.clone(),
+        );
+        sender
+            .send(request)
+            .await
+            .map_err(|_| DeviceError::DeviceNotConnected {
+                device: self.inner_id,
+            })?;
+        Ok(())
+    }
+
+    pub fn master_status(&self) -> Bmv2MasterStatus {
+        self.master_status.load()
+    }
+
     pub fn get_elect(&self) -> Option<(u64, u64)> {
-        match self.master_status {
+        match self.master_status() {
             Bmv2MasterStatus::Elect {
                 election_id_low,
                 election_id_high,
@@ -604,7 +1729,7 @@ impl Bmv2SwitchConnection {
     }
 
     pub fn get_master(&self) -> crate::error::Result<(u64, u64)> {
-        match self.master_status {
+        match self.master_status() {
             Bmv2MasterStatus::Elect {
                 election_id_low,
                 election_id_high,
@@ -621,7 +1746,7 @@ impl Bmv2SwitchConnection {
             } => {
                 return Ok((election_id_low, election_id_high));
             }
-            Bmv2MasterStatus::NotMaster {} => {
+            Bmv2MasterStatus::NotMaster { .. } => {
                 return Err(DeviceError::NotMaster {
                     device: self.inner_id,
                     reason: "Not master".to_owned(),
@@ -648,9 +1773,21 @@ pub enum Bmv2StreamStatus {
     Streaming(tokio::sync::mpsc::Sender<rusty_p4_proto::proto::v1::StreamMessageRequest>),
 }
 
-#[derive(Clone)]
+/// What [Bmv2SwitchConnection::set_forwarding_pipeline_config] did to the device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PipelineConfigUpdate {
+    /// the device already has the same pipeline config, nothing is changed.
+    Unchanged,
+    /// pushed with `RECONCILE_AND_COMMIT`, the forwarding state is kept.
+    Reconciled,
+    /// pushed with `VERIFY_AND_COMMIT`, the forwarding state is cleared.
+    Committed,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Bmv2MasterStatus {
     NoElect,
+    /// arbitration sent, waiting for the result.
     Elect {
         election_id_low: u64,
         election_id_high: u64,
@@ -659,7 +1796,11 @@ pub enum Bmv2MasterStatus {
         election_id_low: u64,
         election_id_high: u64,
     },
-    NotMaster {},
+    /// another controller with a higher election id is the master.
+    NotMaster {
+        election_id_low: u64,
+        election_id_high: u64,
+    },
 }
 
 impl Bmv2MasterStatus {
@@ -672,6 +1813,25 @@ impl Bmv2MasterStatus {
             None => Self::NoElect,
         }
     }
+
+    /// Our election id, `None` if we do not take part in the election.
+    pub fn election_id(&self) -> Option<(u64, u64)> {
+        match *self {
+            Bmv2MasterStatus::NoElect => None,
+            Bmv2MasterStatus::Elect {
+                election_id_low,
+                election_id_high,
+            }
+            | Bmv2MasterStatus::Master {
+                election_id_low,
+                election_id_high,
+            }
+            | Bmv2MasterStatus::NotMaster {
+                election_id_low,
+                election_id_high,
+            } => Some((election_id_low, election_id_high)),
+        }
+    }
 }
 
 #[derive(Debug)]
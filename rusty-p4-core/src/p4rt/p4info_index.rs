@@ -0,0 +1,344 @@
+use crate::proto::p4config::{
+    action, controller_packet_metadata, p4_new_type_spec, p4_new_type_translation, Action,
+    ActionProfile, ControllerPacketMetadata, Counter, Digest, DirectCounter, DirectMeter,
+    MatchField, Meter, P4Info, P4SerializableEnumTypeSpec, Preamble, Register, Table,
+};
+use crate::util::value::Decode;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+/// The P4Runtime representation of a type with `@p4runtime_translation`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdnType {
+    /// a bitstring of the given width, independent of the width in the P4 program.
+    Bitwidth(i32),
+    /// an arbitrary string, which is sent as is.
+    String,
+}
+
+/// A P4Info with precomputed lookups by name, alias and id,
+/// so building and decoding flows does not scan the P4Info.
+#[derive(Debug, Default)]
+pub struct P4InfoIndex {
+    p4info: P4Info,
+    tables: NameIdIndex,
+    actions: NameIdIndex,
+    action_profiles: NameIdIndex,
+    counters: NameIdIndex,
+    direct_counters: NameIdIndex,
+    meters: NameIdIndex,
+    direct_meters: NameIdIndex,
+    registers: NameIdIndex,
+    digests: NameIdIndex,
+    /// the controller headers `packet_in` and `packet_out`.
+    controller_packet_metadata: NameIdIndex,
+    /// match fields of each table, in the order of `p4info.tables`.
+    match_fields: Vec<NameIdIndex>,
+    /// params of each action, in the order of `p4info.actions`.
+    params: Vec<NameIdIndex>,
+    /// fields of each controller header, in the order of `p4info.controller_packet_metadata`.
+    packet_metadata: Vec<NameIdIndex>,
+}
+
+/// Positions of objects in a P4Info list, by name, alias and id.
+#[derive(Debug, Default)]
+struct NameIdIndex {
+    by_name: HashMap<String, usize>,
+    by_id: HashMap<u32, usize>,
+}
+
+impl NameIdIndex {
+    fn from_preambles<'a, I>(preambles: I) -> NameIdIndex
+    where
+        I: Iterator<Item = Option<&'a Preamble>>,
+    {
+        let mut index = NameIdIndex::default();
+        for (position, preamble) in preambles.enumerate() {
+            if let Some(pre) = preamble {
+                index.insert(position, &pre.name, &pre.alias, pre.id);
+            }
+        }
+        index
+    }
+
+    fn insert(&mut self, position: usize, name: &str, alias: &str, id: u32) {
+        // a name wins over an alias of another object.
+        if !alias.is_empty() {
+            self.by_name.entry(alias.to_owned()).or_insert(position);
+        }
+        self.by_name.insert(name.to_owned(), position);
+        self.by_id.insert(id, position);
+    }
+
+    fn get<'a, T>(&self, items: &'a [T], name: &str) -> Option<&'a T> {
+        self.by_name.get(name).and_then(|&i| items.get(i))
+    }
+
+    fn get_by_id<'a, T>(&self, items: &'a [T], id: u32) -> Option<&'a T> {
+        self.by_id.get(&id).and_then(|&i| items.get(i))
+    }
+}
+
+fn preamble_id(preamble: &Option<Preamble>) -> Option<u32> {
+    preamble.as_ref().map(|pre| pre.id)
+}
+
+impl P4InfoIndex {
+    pub fn new(p4info: P4Info) -> P4InfoIndex {
+        let match_fields = p4info
+            .tables
+            .iter()
+            .map(|table| {
+                let mut index = NameIdIndex::default();
+                for (position, field) in table.match_fields.iter().enumerate() {
+                    index.insert(position, &field.name, "", field.id);
+                }
+                index
+            })
+            .collect();
+        let params = p4info
+            .actions
+            .iter()
+            .map(|action| {
+                let mut index = NameIdIndex::default();
+                for (position, param) in action.params.iter().enumerate() {
+                    index.insert(position, &param.name, "", param.id);
+                }
+                index
+            })
+            .collect();
+        let packet_metadata = p4info
+            .controller_packet_metadata
+            .iter()
+            .map(|header| {
+                let mut index = NameIdIndex::default();
+                for (position, field) in header.metadata.iter().enumerate() {
+                    index.insert(position, &field.name, "", field.id);
+                }
+                index
+            })
+            .collect();
+        P4InfoIndex {
+            tables: NameIdIndex::from_preambles(p4info.tables.iter().map(|x| x.preamble.as_ref())),
+            actions: NameIdIndex::from_preambles(
+                p4info.actions.iter().map(|x| x.preamble.as_ref()),
+            ),
+            action_profiles: NameIdIndex::from_preambles(
+                p4info.action_profiles.iter().map(|x| x.preamble.as_ref()),
+            ),
+            counters: NameIdIndex::from_preambles(
+                p4info.counters.iter().map(|x| x.preamble.as_ref()),
+            ),
+            direct_counters: NameIdIndex::from_preambles(
+                p4info.direct_counters.iter().map(|x| x.preamble.as_ref()),
+            ),
+            meters: NameIdIndex::from_preambles(p4info.meters.iter().map(|x| x.preamble.as_ref())),
+            direct_meters: NameIdIndex::from_preambles(
+                p4info.direct_meters.iter().map(|x| x.preamble.as_ref()),
+            ),
+            registers: NameIdIndex::from_preambles(
+                p4info.registers.iter().map(|x| x.preamble.as_ref()),
+            ),
+            digests: NameIdIndex::from_preambles(
+                p4info.digests.iter().map(|x| x.preamble.as_ref()),
+            ),
+            controller_packet_metadata: NameIdIndex::from_preambles(
+                p4info
+                    .controller_packet_metadata
+                    .iter()
+                    .map(|x| x.preamble.as_ref()),
+            ),
+            match_fields,
+            params,
+            packet_metadata,
+            p4info,
+        }
+    }
+
+    pub fn p4info(&self) -> &P4Info {
+        &self.p4info
+    }
+
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(&self.p4info.tables, name)
+    }
+
+    pub fn table_id(&self, name: &str) -> Option<u32> {
+        self.table(name).and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn table_by_id(&self, id: u32) -> Option<&Table> {
+        self.tables.get_by_id(&self.p4info.tables, id)
+    }
+
+    pub fn match_field(&self, table: &str, name: &str) -> Option<&MatchField> {
+        let position = *self.tables.by_name.get(table)?;
+        self.match_fields[position].get(&self.p4info.tables[position].match_fields, name)
+    }
+
+    pub fn match_field_by_id(&self, table_id: u32, id: u32) -> Option<&MatchField> {
+        let position = *self.tables.by_id.get(&table_id)?;
+        self.match_fields[position].get_by_id(&self.p4info.tables[position].match_fields, id)
+    }
+
+    pub fn action(&self, name: &str) -> Option<&Action> {
+        self.actions.get(&self.p4info.actions, name)
+    }
+
+    pub fn action_id(&self, name: &str) -> Option<u32> {
+        self.action(name).and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn action_by_id(&self, id: u32) -> Option<&Action> {
+        self.actions.get_by_id(&self.p4info.actions, id)
+    }
+
+    pub fn action_param(&self, action: &str, name: &str) -> Option<&action::Param> {
+        let position = *self.actions.by_name.get(action)?;
+        self.params[position].get(&self.p4info.actions[position].params, name)
+    }
+
+    pub fn action_param_by_id(&self, action_id: u32, id: u32) -> Option<&action::Param> {
+        let position = *self.actions.by_id.get(&action_id)?;
+        self.params[position].get_by_id(&self.p4info.actions[position].params, id)
+    }
+
+    pub fn action_profile(&self, name: &str) -> Option<&ActionProfile> {
+        self.action_profiles.get(&self.p4info.action_profiles, name)
+    }
+
+    pub fn action_profile_id(&self, name: &str) -> Option<u32> {
+        self.action_profile(name)
+            .and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn action_profile_by_id(&self, id: u32) -> Option<&ActionProfile> {
+        self.action_profiles
+            .get_by_id(&self.p4info.action_profiles, id)
+    }
+
+    pub fn counter(&self, name: &str) -> Option<&Counter> {
+        self.counters.get(&self.p4info.counters, name)
+    }
+
+    pub fn counter_id(&self, name: &str) -> Option<u32> {
+        self.counter(name).and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn direct_counter(&self, name: &str) -> Option<&DirectCounter> {
+        self.direct_counters.get(&self.p4info.direct_counters, name)
+    }
+
+    pub fn meter(&self, name: &str) -> Option<&Meter> {
+        self.meters.get(&self.p4info.meters, name)
+    }
+
+    pub fn meter_id(&self, name: &str) -> Option<u32> {
+        self.meter(name).and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn direct_meter(&self, name: &str) -> Option<&DirectMeter> {
+        self.direct_meters.get(&self.p4info.direct_meters, name)
+    }
+
+    pub fn register(&self, name: &str) -> Option<&Register> {
+        self.registers.get(&self.p4info.registers, name)
+    }
+
+    pub fn register_id(&self, name: &str) -> Option<u32> {
+        self.register(name).and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn digest(&self, name: &str) -> Option<&Digest> {
+        self.digests.get(&self.p4info.digests, name)
+    }
+
+    pub fn digest_id(&self, name: &str) -> Option<u32> {
+        self.digest(name).and_then(|x| preamble_id(&x.preamble))
+    }
+
+    pub fn digest_by_id(&self, id: u32) -> Option<&Digest> {
+        self.digests.get_by_id(&self.p4info.digests, id)
+    }
+
+    /// The controller header `packet_in` or `packet_out`.
+    pub fn controller_packet_metadata(&self, name: &str) -> Option<&ControllerPacketMetadata> {
+        self.controller_packet_metadata
+            .get(&self.p4info.controller_packet_metadata, name)
+    }
+
+    /// A field of the controller header `header`, with its bitwidth and type.
+    pub fn packet_metadata(
+        &self,
+        header: &str,
+        name: &str,
+    ) -> Option<&controller_packet_metadata::Metadata> {
+        let position = *self.controller_packet_metadata.by_name.get(header)?;
+        self.packet_metadata[position].get(
+            &self.p4info.controller_packet_metadata[position].metadata,
+            name,
+        )
+    }
+
+    pub fn packet_metadata_by_id(
+        &self,
+        header: &str,
+        id: u32,
+    ) -> Option<&controller_packet_metadata::Metadata> {
+        let position = *self.controller_packet_metadata.by_name.get(header)?;
+        self.packet_metadata[position].get_by_id(
+            &self.p4info.controller_packet_metadata[position].metadata,
+            id,
+        )
+    }
+
+    /// The P4Runtime representation of the named type `type_name`,
+    /// `None` if the type is not translated.
+    pub fn sdn_type(&self, type_name: &str) -> Option<SdnType> {
+        let new_type = self.p4info.type_info.as_ref()?.new_types.get(type_name)?;
+        match new_type.representation.as_ref()? {
+            p4_new_type_spec::Representation::TranslatedType(translated) => {
+                match translated.sdn_type.as_ref()? {
+                    p4_new_type_translation::SdnType::SdnBitwidth(w) => Some(SdnType::Bitwidth(*w)),
+                    p4_new_type_translation::SdnType::SdnString(_) => Some(SdnType::String),
+                }
+            }
+            p4_new_type_spec::Representation::OriginalType(_) => None,
+        }
+    }
+
+    pub fn serializable_enum(&self, type_name: &str) -> Option<&P4SerializableEnumTypeSpec> {
+        self.p4info
+            .type_info
+            .as_ref()?
+            .serializable_enums
+            .get(type_name)
+    }
+
+    /// Map a member name of the serializable enum `type_name` to its value.
+    /// Values which are not a member name are kept unchanged.
+    pub fn enum_to_sdn(&self, type_name: &str, value: &Bytes) -> Bytes {
+        self.serializable_enum(type_name)
+            .and_then(|spec| {
+                spec.members
+                    .iter()
+                    .find(|m| m.name.as_bytes() == &value[..])
+            })
+            .map(|m| m.value.clone())
+            .unwrap_or_else(|| value.clone())
+    }
+
+    /// Map a value of the serializable enum `type_name` to its member name.
+    /// Values which are not a member value are kept unchanged.
+    pub fn enum_from_sdn(&self, type_name: &str, value: &Bytes) -> Bytes {
+        let number = u128::decode(value);
+        self.serializable_enum(type_name)
+            .and_then(|spec| {
+                spec.members
+                    .iter()
+                    .find(|m| number.is_some() && u128::decode(&m.value) == number)
+            })
+            .map(|m| Bytes::copy_from_slice(m.name.as_bytes()))
+            .unwrap_or_else(|| value.clone())
+    }
+}
@@ -1,7 +1,10 @@
+use crate::p4rt::p4info_index::P4InfoIndex;
 use crate::p4rt::pure::{get_packin_egress_port_metaid, get_packout_egress_port_metaid};
 use crate::proto::p4config::P4Info;
+use bytes::Bytes;
 use log::error;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
@@ -12,10 +15,25 @@ pub trait Pipeconf: Send + Sync {
     fn get_id(&self) -> PipeconfID;
     fn get_name(&self) -> &str;
     fn get_p4info(&self) -> &P4Info;
+    /// The P4Info with lookups by name and id, used to build and decode entities.
+    fn get_p4info_index(&self) -> &P4InfoIndex;
     fn get_bmv2_file_path(&self) -> &Path;
     fn get_any_behaviour(&self, name: &str) -> Box<dyn std::any::Any>;
     fn get_packetin_ingress_id(&self) -> u32;
     fn get_packetout_egress_id(&self) -> u32;
+
+    /// Translate a controller-side value of the P4Info named type `type_name` to its P4Runtime
+    /// representation, e.g. a port name of a type with `@p4runtime_translation` to a port id.
+    /// `None` if the value has no translation.
+    /// By default, member names of serializable enums are translated to their values.
+    fn translate_to_sdn(&self, type_name: &str, value: &Bytes) -> Option<Bytes> {
+        Some(self.get_p4info_index().enum_to_sdn(type_name, value))
+    }
+
+    /// The reverse of `translate_to_sdn`, for values read from devices.
+    fn translate_from_sdn(&self, type_name: &str, value: &Bytes) -> Option<Bytes> {
+        Some(self.get_p4info_index().enum_from_sdn(type_name, value))
+    }
 }
 
 impl Pipeconf for &Arc<dyn Pipeconf> {
@@ -31,6 +49,10 @@ impl Pipeconf for &Arc<dyn Pipeconf> {
         self.as_ref().get_p4info()
     }
 
+    fn get_p4info_index(&self) -> &P4InfoIndex {
+        self.as_ref().get_p4info_index()
+    }
+
     fn get_bmv2_file_path(&self) -> &Path {
         self.as_ref().get_bmv2_file_path()
     }
@@ -46,6 +68,14 @@ impl Pipeconf for &Arc<dyn Pipeconf> {
     fn get_packetout_egress_id(&self) -> u32 {
         self.as_ref().get_packetout_egress_id()
     }
+
+    fn translate_to_sdn(&self, type_name: &str, value: &Bytes) -> Option<Bytes> {
+        self.as_ref().translate_to_sdn(type_name, value)
+    }
+
+    fn translate_from_sdn(&self, type_name: &str, value: &Bytes) -> Option<Bytes> {
+        self.as_ref().translate_from_sdn(type_name, value)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -53,10 +83,18 @@ pub struct DefaultPipeconf {
     id: PipeconfID,
     name: String,
     inner: Arc<Inner>,
+    translations: Arc<HashMap<String, ValueTranslation>>,
     pub packetout_egress_id: u32,
     pub packetin_ingress_id: u32,
 }
 
+/// Values of a translated type, controller-side to P4Runtime and back.
+#[derive(Clone, Debug, Default)]
+struct ValueTranslation {
+    to_sdn: HashMap<Bytes, Bytes>,
+    from_sdn: HashMap<Bytes, Bytes>,
+}
+
 impl DefaultPipeconf {
     pub fn new<T: AsRef<Path> + Debug>(
         name: &str,
@@ -71,24 +109,55 @@ impl DefaultPipeconf {
         let mut file = std::fs::File::open(p4info_file_path).unwrap();
         let mut buf = vec![];
         file.read_to_end(&mut buf).unwrap();
-        let p4info = prost::Message::decode(buf.as_ref()).unwrap();
-        let packetout_id = get_packout_egress_port_metaid(&p4info).unwrap();
-        let packetin_id = get_packin_egress_port_metaid(&p4info).unwrap();
+        let p4info: P4Info = prost::Message::decode(buf.as_ref()).unwrap();
+        Self::from_p4info(name, p4info, bmv2_file_path)
+    }
+
+    /// Build a pipeconf from a decoded P4Info.
+    /// The packet metadata ids are 0 if the P4Info has no `ingress_port` or `egress_port` metadata.
+    pub fn from_p4info<T: AsRef<Path>>(
+        name: &str,
+        p4info: P4Info,
+        bmv2_file_path: T,
+    ) -> DefaultPipeconf {
+        let packetout_id = get_packout_egress_port_metaid(&p4info).unwrap_or(0);
+        let packetin_id = get_packin_egress_port_metaid(&p4info).unwrap_or(0);
         let id = crate::util::hash(name);
         DefaultPipeconf {
             id: PipeconfID(id),
             name: name.to_owned(),
             inner: Arc::new(Inner {
-                p4info,
+                p4info: P4InfoIndex::new(p4info),
                 bmv2_json_file_path: PathBuf::from(bmv2_file_path.as_ref()),
             }),
+            translations: Arc::new(HashMap::new()),
             packetout_egress_id: packetout_id,
             packetin_ingress_id: packetin_id,
         }
     }
 
+    /// Translate values of the P4Info named type `type_name`, e.g. port names to the port ids
+    /// of a Stratum target. Each pair is a controller-side value and its P4Runtime representation,
+    /// which for a `sdn_bitwidth` type is padded to that width.
+    pub fn with_translation<I, K, V>(mut self, type_name: &str, pairs: I) -> DefaultPipeconf
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<Bytes>,
+        V: Into<Bytes>,
+    {
+        let translation = Arc::make_mut(&mut self.translations)
+            .entry(type_name.to_owned())
+            .or_default();
+        for (controller, sdn) in pairs {
+            let (controller, sdn) = (controller.into(), sdn.into());
+            translation.to_sdn.insert(controller.clone(), sdn.clone());
+            translation.from_sdn.insert(sdn, controller);
+        }
+        self
+    }
+
     pub fn get_p4info(&self) -> &P4Info {
-        &self.inner.p4info
+        self.inner.p4info.p4info()
     }
 
     pub fn get_bmv2_file_path(&self) -> &Path {
@@ -102,7 +171,7 @@ impl DefaultPipeconf {
 
 #[derive(Debug)]
 struct Inner {
-    pub p4info: P4Info,
+    pub p4info: P4InfoIndex,
     pub bmv2_json_file_path: PathBuf,
 }
 
@@ -119,6 +188,10 @@ impl Pipeconf for DefaultPipeconf {
     }
 
     fn get_p4info(&self) -> &P4Info {
+        self.inner.p4info.p4info()
+    }
+
+    fn get_p4info_index(&self) -> &P4InfoIndex {
         &self.inner.p4info
     }
 
@@ -137,6 +210,20 @@ impl Pipeconf for DefaultPipeconf {
     fn get_packetout_egress_id(&self) -> u32 {
         self.packetout_egress_id
     }
+
+    fn translate_to_sdn(&self, type_name: &str, value: &Bytes) -> Option<Bytes> {
+        match self.translations.get(type_name) {
+            Some(translation) => translation.to_sdn.get(value).cloned(),
+            None => Some(self.get_p4info_index().enum_to_sdn(type_name, value)),
+        }
+    }
+
+    fn translate_from_sdn(&self, type_name: &str, value: &Bytes) -> Option<Bytes> {
+        match self.translations.get(type_name) {
+            Some(translation) => translation.from_sdn.get(value).cloned(),
+            None => Some(self.get_p4info_index().enum_from_sdn(type_name, value)),
+        }
+    }
 }
 
 impl Pipeconf {
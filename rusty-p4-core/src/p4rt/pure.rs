@@ -1,57 +1,41 @@
 use log::{debug, error, info, trace, warn};
 
+use super::p4info_index::{P4InfoIndex, SdnType};
 use super::pipeconf::Pipeconf;
 use crate::entity::UpdateType;
-use crate::error::Result;
+use crate::error::{Result, ValidationError};
 use crate::p4rt::bmv2::Bmv2MasterUpdateOption;
 use crate::p4rt::pipeconf::DefaultPipeconf;
 use crate::proto::p4config::P4Info;
 use crate::proto::p4config::*;
 use crate::proto::p4runtime::{
-    field_match, stream_message_request, FieldMatch, StreamMessageRequest, TableEntry, WriteRequest,
+    field_match, stream_message_request, FieldMatch, ReadRequest, StreamMessageRequest, TableEntry,
+    WriteRequest,
+};
+use crate::util::flow::{
+    Flow, FlowAction, FlowActionParam, FlowMatch, FlowTable, FlowTableAction, FlowWeightedAction,
+};
+use crate::util::value::{
+    canonical_bytestring, decode_bytestring, Decode, Encode, InnerParamValue, InnerValue,
 };
-use crate::util::flow::{Flow, FlowAction, FlowActionParam, FlowMatch, FlowTable};
-use crate::util::value::{Encode, InnerParamValue, InnerValue};
 use byteorder::BigEndian;
 use byteorder::ByteOrder;
 use bytes::{Bytes, BytesMut};
 use futures::{Future, Sink, StreamExt};
-use rusty_p4_proto::proto::v1::field_match::{Exact, FieldMatchType, Lpm, Range, Ternary};
+use rusty_p4_proto::proto::v1::field_match::{
+    Exact, FieldMatchType, Lpm, Optional, Range, Ternary,
+};
 use rusty_p4_proto::proto::v1::{
-    Entity, Index, MasterArbitrationUpdate, MeterConfig, MeterEntry, PacketMetadata, PacketOut,
-    TableAction, Uint128, Update,
+    forwarding_pipeline_config, DigestListAck, Entity, Index, MasterArbitrationUpdate, MeterConfig,
+    MeterEntry, PacketMetadata, PacketOut, Role, TableAction, Uint128, Update,
 };
+use smallvec::SmallVec;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::io::AsyncReadExt;
 
-pub fn new_write_table_entry(
-    device_id: u64,
-    table_entry: TableEntry,
-    update: UpdateType,
-) -> WriteRequest {
-    let update_type = if table_entry.is_default_action {
-        crate::proto::p4runtime::update::Type::Modify
-    } else {
-        update.into()
-    };
-    let mut request = crate::proto::p4runtime::WriteRequest {
-        device_id,
-        role_id: 0,
-        election_id: Some(Uint128 { high: 0, low: 1 }),
-        updates: vec![Update {
-            r#type: update_type as i32,
-            entity: Some(Entity {
-                entity: Some(crate::proto::p4runtime::entity::Entity::TableEntry(
-                    table_entry.clone(),
-                )),
-            }),
-        }],
-        atomicity: 0,
-    };
-    request
-}
-
+#[deprecated(note = "use `canonical_bytestring`, which fails instead of dropping set bits")]
 pub fn adjust_value(mut value: Bytes, bytes_len: usize) -> Bytes {
     if bytes_len == value.len() {
         value
@@ -64,6 +48,7 @@ pub fn adjust_value(mut value: Bytes, bytes_len: usize) -> Bytes {
     }
 }
 
+#[deprecated(note = "use `canonical_bytestring`, which fails instead of dropping set bits")]
 pub fn adjust_value_with(value: Bytes, bytes_len: usize, e: u8) -> Bytes {
     if bytes_len == value.len() {
         value
@@ -76,23 +61,13 @@ pub fn adjust_value_with(value: Bytes, bytes_len: usize, e: u8) -> Bytes {
     }
 }
 
-pub fn new_packet_out_request<T>(
-    pipeconf: &T,
-    egress_port: u32,
+pub fn new_packet_out_request(
+    metadata: Vec<PacketMetadata>,
     packet: Bytes,
-) -> StreamMessageRequest
-where
-    T: Pipeconf,
-{
+) -> StreamMessageRequest {
     let packetOut = PacketOut {
         payload: packet,
-        metadata: vec![PacketMetadata {
-            metadata_id: pipeconf.get_packetout_egress_id(),
-            value: adjust_value(
-                Bytes::copy_from_slice(egress_port.to_be_bytes().as_ref()),
-                2,
-            ),
-        }],
+        metadata,
     };
     let request = StreamMessageRequest {
         update: Some(stream_message_request::Update::Packet(packetOut)),
@@ -100,131 +75,433 @@ where
     request
 }
 
-pub fn new_set_entity_request(
+pub fn new_write_request(
     device_id: u64,
-    entity: Entity,
-    update_type: crate::proto::p4runtime::update::Type,
+    role_id: u64,
+    election_id: (u64, u64),
+    updates: Vec<Update>,
+    atomicity: crate::proto::p4runtime::write_request::Atomicity,
 ) -> WriteRequest {
     WriteRequest {
         device_id,
-        role_id: 0,
-        election_id: Some(Uint128 { high: 0, low: 1 }),
-        updates: vec![Update {
-            r#type: update_type as i32,
-            entity: Some(entity),
-        }],
-        atomicity: 0,
+        role_id,
+        election_id: Some(Uint128 {
+            high: election_id.1,
+            low: election_id.0,
+        }),
+        updates,
+        atomicity: atomicity as i32,
     }
 }
 
-pub fn build_table_entry(
-    p4info: &P4Info,
+/// Build a p4runtime action, `None` if the action name is empty.
+pub fn build_action<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    action: &FlowAction,
+) -> std::result::Result<Option<crate::proto::p4runtime::Action>, ValidationError> {
+    if action.name.is_empty() {
+        return Ok(None);
+    }
+    let action_id = pipeconf
+        .get_p4info_index()
+        .action_id(&action.name)
+        .ok_or_else(|| ValidationError::UnknownName {
+            kind: "action",
+            name: action.name.to_string(),
+        })?;
+    let mut p4runtime_action = crate::proto::p4runtime::Action {
+        action_id,
+        params: vec![],
+    };
+    for p in action.params.iter() {
+        p4runtime_action.params.push(get_action_param_pb(
+            pipeconf,
+            &action.name,
+            &p.name,
+            p.value.clone(),
+        )?);
+    }
+    Ok(Some(p4runtime_action))
+}
+
+pub fn build_table_action<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    action: &FlowTableAction,
+) -> std::result::Result<Option<crate::proto::p4runtime::table_action::Type>, ValidationError> {
+    use crate::proto::p4runtime::table_action::Type;
+
+    Ok(match action {
+        FlowTableAction::Action(action) => build_action(pipeconf, action)?.map(Type::Action),
+        FlowTableAction::Member(member_id) => Some(Type::ActionProfileMemberId(*member_id)),
+        FlowTableAction::Group(group_id) => Some(Type::ActionProfileGroupId(*group_id)),
+        FlowTableAction::ActionSet(actions) => {
+            let mut action_profile_actions = Vec::with_capacity(actions.len());
+            for a in actions.iter() {
+                action_profile_actions.push(crate::proto::p4runtime::ActionProfileAction {
+                    action: build_action(pipeconf, &a.action)?,
+                    weight: a.weight,
+                    watch: a.watch,
+                });
+            }
+            Some(Type::ActionProfileActionSet(
+                crate::proto::p4runtime::ActionProfileActionSet {
+                    action_profile_actions,
+                },
+            ))
+        }
+    })
+}
+
+/// Build a table entry, checking the names, match kinds and value widths against P4Info.
+pub fn build_table_entry<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
     table_name: &str,
     match_fields: &[FlowMatch],
     default_action: bool,
-    action_name: &str,
-    action_params: &[FlowActionParam],
+    action: &FlowTableAction,
     priority: i32,
     metadata: u64,
-) -> TableEntry {
-    let action = if !action_name.is_empty() {
-        let action_id = get_actions_id(p4info, action_name);
-        let action_id = if action_id.is_none() {
-            panic!("action with name '{}' not found.", action_name);
-        } else {
-            action_id.unwrap()
-        };
-        let mut action = crate::proto::p4runtime::Action {
-            action_id: get_actions_id(p4info, action_name).unwrap(),
-            params: vec![],
-        };
-        if !action_params.is_empty() {
-            for p in action_params {
-                action.params.push(get_action_param_pb(
-                    p4info,
-                    action_name,
-                    p.name,
-                    p.value.clone(),
-                ));
-            }
+    idle_timeout_ns: i64,
+) -> std::result::Result<TableEntry, ValidationError> {
+    let table = pipeconf
+        .get_p4info_index()
+        .table(table_name)
+        .ok_or_else(|| ValidationError::UnknownName {
+            kind: "table",
+            name: table_name.to_owned(),
+        })?;
+    if !default_action && priority <= 0 && table_requires_priority(table) {
+        return Err(ValidationError::PriorityRequired {
+            table: table_name.to_owned(),
+        });
+    }
+    let action = build_table_action(pipeconf, action)?;
+    Ok(TableEntry {
+        action: Some(TableAction { r#type: action }),
+        controller_metadata: metadata,
+        is_default_action: default_action,
+        idle_timeout_ns,
+        ..build_table_entry_match(pipeconf, table_name, match_fields, priority)?
+    })
+}
+
+/// Build a table entry with only the table id, match fields and priority, which identify an entry,
+/// e.g. to read or write the direct counter or meter of it without repeating the action.
+pub fn build_table_entry_match<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    table_name: &str,
+    match_fields: &[FlowMatch],
+    priority: i32,
+) -> std::result::Result<TableEntry, ValidationError> {
+    let table_id = pipeconf
+        .get_p4info_index()
+        .table_id(table_name)
+        .ok_or_else(|| ValidationError::UnknownName {
+            kind: "table",
+            name: table_name.to_owned(),
+        })?;
+    let mut table_entry = TableEntry {
+        table_id,
+        priority,
+        ..Default::default()
+    };
+
+    for m in match_fields {
+        if let Some(entry) = get_match_field_pb(pipeconf, table_name, &m.name, &m.value)? {
+            table_entry.r#match.push(entry)
         }
-        Some(action)
+    }
+
+    Ok(table_entry)
+}
+
+/// Entries of a table with ternary, range or optional match fields are ordered by priority, which must be positive.
+fn table_requires_priority(table: &Table) -> bool {
+    table.match_fields.iter().any(|field| match field.r#match {
+        Some(match_field::Match::MatchType(kind)) => matches!(
+            match_field::MatchType::from_i32(kind),
+            Some(match_field::MatchType::Ternary)
+                | Some(match_field::MatchType::Range)
+                | Some(match_field::MatchType::Optional)
+        ),
+        _ => false,
+    })
+}
+
+/// Encode a value as the canonical bytestring of a field or param with `bitwidth` bits.
+fn fit_value(
+    name: &str,
+    value: &Bytes,
+    bitwidth: i32,
+) -> std::result::Result<Bytes, ValidationError> {
+    canonical_bytestring(value, bitwidth).ok_or_else(|| ValidationError::ValueTooWide {
+        name: name.to_owned(),
+        bitwidth,
+    })
+}
+
+/// The width of a field or param in P4Runtime, which is the `sdn_bitwidth` of a translated type.
+fn sdn_bitwidth(index: &P4InfoIndex, type_name: Option<&P4NamedType>, bitwidth: i32) -> i32 {
+    match type_name.and_then(|t| index.sdn_type(&t.name)) {
+        Some(SdnType::Bitwidth(sdn_bitwidth)) => sdn_bitwidth,
+        _ => bitwidth,
+    }
+}
+
+fn is_sdn_string(index: &P4InfoIndex, type_name: Option<&P4NamedType>) -> bool {
+    type_name.and_then(|t| index.sdn_type(&t.name)) == Some(SdnType::String)
+}
+
+fn translate_to_sdn<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    name: &str,
+    type_name: &str,
+    value: &Bytes,
+) -> std::result::Result<Bytes, ValidationError> {
+    pipeconf
+        .translate_to_sdn(type_name, value)
+        .ok_or_else(|| ValidationError::Untranslatable {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+        })
+}
+
+/// Translate a value of a param or packet metadata of the named type `type_name`,
+/// and encode it as the canonical bytestring of its P4Runtime representation.
+fn encode_typed_value<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    name: &str,
+    type_name: Option<&P4NamedType>,
+    bitwidth: i32,
+    value: &Bytes,
+) -> std::result::Result<Bytes, ValidationError> {
+    let type_name = match type_name {
+        Some(type_name) => type_name,
+        None => return fit_value(name, value, bitwidth),
+    };
+    let index = pipeconf.get_p4info_index();
+    let value = translate_to_sdn(pipeconf, name, &type_name.name, value)?;
+    if is_sdn_string(index, Some(type_name)) {
+        Ok(value)
     } else {
-        None
+        fit_value(name, &value, sdn_bitwidth(index, Some(type_name), bitwidth))
+    }
+}
+
+/// Decode a value read from a device to the full width of its field or param,
+/// and translate it back if its type is translated. Untranslatable values are kept as read.
+fn decode_typed_value<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    type_name: Option<&P4NamedType>,
+    bitwidth: i32,
+    value: &Bytes,
+) -> Option<Bytes> {
+    let index = pipeconf.get_p4info_index();
+    let value = if is_sdn_string(index, type_name) {
+        value.clone()
+    } else {
+        decode_bytestring(value, sdn_bitwidth(index, type_name, bitwidth))?
     };
+    Some(match type_name {
+        Some(type_name) => pipeconf
+            .translate_from_sdn(&type_name.name, &value)
+            .unwrap_or(value),
+        None => value,
+    })
+}
 
-    let mut table_entry = TableEntry {
-        metadata: Bytes::new(),
-        table_id: get_table_id(p4info, table_name).unwrap(),
-        r#match: vec![],
-        action: Some(TableAction {
-            r#type: action.map(crate::proto::p4runtime::table_action::Type::Action),
+/// Rebuild a flow from a table entry read from a device.
+/// Fails if the entry refers to a table, match field, action or param that is not in P4Info,
+/// or if a value does not fit its field.
+pub fn table_entry_to_flow<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    table_entry: &TableEntry,
+) -> std::result::Result<Flow, ValidationError> {
+    let index = pipeconf.get_p4info_index();
+    let table_name = index
+        .table_by_id(table_entry.table_id)
+        .and_then(|table| table.preamble.as_ref())
+        .map(|pre| pre.name.as_str())
+        .ok_or(ValidationError::UnknownId {
+            kind: "table",
+            id: table_entry.table_id,
+        })?;
+
+    let mut flow_matches: SmallVec<[FlowMatch; 3]> = SmallVec::new();
+    for m in table_entry.r#match.iter() {
+        let match_field = index
+            .match_field_by_id(table_entry.table_id, m.field_id)
+            .ok_or(ValidationError::UnknownId {
+                kind: "match field",
+                id: m.field_id,
+            })?;
+        // devices return canonical bytestrings, decode them to the full width of the field.
+        let type_name = match_field.type_name.as_ref();
+        let bitwidth = sdn_bitwidth(index, type_name, match_field.bitwidth);
+        let too_wide = || ValidationError::ValueTooWide {
+            name: match_field.name.clone(),
+            bitwidth,
+        };
+        let decode = |value: &Bytes| {
+            decode_typed_value(pipeconf, type_name, match_field.bitwidth, value)
+                .ok_or_else(too_wide)
+        };
+        let value = match m.field_match_type.as_ref() {
+            Some(FieldMatchType::Exact(Exact { value })) => InnerValue::EXACT(decode(value)?),
+            Some(FieldMatchType::Ternary(Ternary { value, mask })) => InnerValue::TERNARY(
+                decode(value)?,
+                decode_bytestring(mask, bitwidth).ok_or_else(too_wide)?,
+            ),
+            Some(FieldMatchType::Lpm(Lpm { value, prefix_len })) => {
+                InnerValue::LPM(decode(value)?, *prefix_len)
+            }
+            Some(FieldMatchType::Range(Range { low, high })) => {
+                InnerValue::RANGE(decode(low)?, decode(high)?)
+            }
+            Some(FieldMatchType::Optional(Optional { value })) => {
+                InnerValue::OPTIONAL(decode(value)?)
+            }
+            _ => {
+                return Err(ValidationError::MatchKindMismatch {
+                    field: match_field.name.clone(),
+                    expected: match match_field.r#match.as_ref() {
+                        Some(match_field::Match::MatchType(kind)) => {
+                            format!("{:?}", match_field::MatchType::from_i32(*kind))
+                        }
+                        _ => "unsupported".to_owned(),
+                    },
+                    actual: "unsupported",
+                })
+            }
+        };
+        flow_matches.push(FlowMatch {
+            name: match_field.name.clone().into(),
+            value,
+        });
+    }
+    flow_matches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    use crate::proto::p4runtime::table_action::Type;
+    let action = match table_entry
+        .action
+        .as_ref()
+        .and_then(|action| action.r#type.as_ref())
+    {
+        Some(Type::Action(action)) => {
+            FlowTableAction::Action(action_to_flow_action(pipeconf, action)?)
+        }
+        Some(Type::ActionProfileMemberId(member_id)) => FlowTableAction::Member(*member_id),
+        Some(Type::ActionProfileGroupId(group_id)) => FlowTableAction::Group(*group_id),
+        Some(Type::ActionProfileActionSet(action_set)) => {
+            let mut actions = SmallVec::new();
+            for a in action_set.action_profile_actions.iter() {
+                // an action set member without action has no action id, which is never in P4Info.
+                let action = a.action.as_ref().ok_or(ValidationError::UnknownId {
+                    kind: "action",
+                    id: 0,
+                })?;
+                actions.push(FlowWeightedAction {
+                    action: action_to_flow_action(pipeconf, action)?,
+                    weight: a.weight,
+                    watch: a.watch,
+                });
+            }
+            FlowTableAction::ActionSet(Arc::new(actions))
+        }
+        None => FlowTableAction::Action(FlowAction {
+            name: "".into(),
+            params: Default::default(),
         }),
-        priority,
-        controller_metadata: metadata,
-        meter_config: None,
-        counter_data: None,
-        is_default_action: default_action,
-        idle_timeout_ns: 0,
-        time_since_last_hit: None,
     };
 
-    for m in match_fields {
-        let entry = get_match_field_pb(p4info, table_name, m.name, &m.value).unwrap();
-        table_entry.r#match.push(entry)
-    }
-
-    table_entry
-}
-
-//pub fn table_entry_to_flow(
-//    p4info: &P4Info,
-//    table_entry: &TableEntry
-//) -> Option<crate::util::flow::Flow> {
-//    let table_id = table_entry.table_id;
-//    let table:&Table = p4info
-//        .tables
-//        .iter()
-//        .filter(|x|x.preamble.is_some())
-//        .find(|x|x.preamble.as_ref().unwrap().id == table_id)?;
-//    let table_name = &table.preamble.as_ref().unwrap().name;
-//    let matches = &table.match_fields;
-//
-//    let mut flow_matches = vec![];
-//    for i in table_entry.r#match {
-//        if i.field_match_type.is_none() {
-//            continue;
-//        }
-//        let m:&MatchField = matches
-//            .iter()
-//            .find(|x|x.id == i.field_id)?;
-//        let match_name = &m.name;
-//        let match_value = match i.field_match_type.unwrap() {
-//            FieldMatchType::Exact(Exact { value }) => { InnerValue::EXACT(Bytes::from(value)) }
-//            FieldMatchType::Ternary(Ternary { value, mask }) => { InnerValue::TERNARY(Bytes::from(value),Bytes::from(mask)) }
-//            FieldMatchType::Lpm(Lpm { value, prefix_len }) => { InnerValue::LPM(Bytes::from(value), prefix_len) }
-//            FieldMatchType::Range(Range { low, high }) => { InnerValue::RANGE(Bytes::from(low), Bytes::from(high)) }
-//            FieldMatchType::Other(_) => {
-//                continue;
-//            }
-//        };
-//        flow_matches.push(FlowMatch {
-//            name: "",
-//            value: match_value
-//        });
-//    }
-//
-//    Flow {
-//        table: Arc::new(FlowTable {
-//            name: table_name,
-//            matches: flow_matches
-//        }),
-//        action: Arc::new(FlowAction { name: "", params: vec![] }),
-//        priority: 0,
-//        metadata: 0
-//    };
-//    unimplemented!()
-//}
+    Ok(Flow {
+        table: FlowTable::new(table_name.to_owned(), Arc::new(flow_matches)),
+        action,
+        priority: table_entry.priority,
+        metadata: table_entry.controller_metadata,
+        idle_timeout_ns: table_entry.idle_timeout_ns,
+    })
+}
+
+/// Rebuild a flow action from a p4runtime action, failing if the action or a param is not in P4Info.
+pub fn action_to_flow_action<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    action: &crate::proto::p4runtime::Action,
+) -> std::result::Result<FlowAction, ValidationError> {
+    let index = pipeconf.get_p4info_index();
+    let action_name = index
+        .action_by_id(action.action_id)
+        .and_then(|action| action.preamble.as_ref())
+        .map(|pre| pre.name.clone())
+        .ok_or(ValidationError::UnknownId {
+            kind: "action",
+            id: action.action_id,
+        })?;
+    let mut params: SmallVec<[FlowActionParam; 3]> = SmallVec::new();
+    for p in action.params.iter() {
+        let param = index
+            .action_param_by_id(action.action_id, p.param_id)
+            .ok_or(ValidationError::UnknownId {
+                kind: "action param",
+                id: p.param_id,
+            })?;
+        let value =
+            decode_typed_value(pipeconf, param.type_name.as_ref(), param.bitwidth, &p.value)
+                .ok_or_else(|| ValidationError::ValueTooWide {
+                    name: param.name.clone(),
+                    bitwidth: sdn_bitwidth(index, param.type_name.as_ref(), param.bitwidth),
+                })?;
+        params.push(FlowActionParam {
+            name: param.name.clone().into(),
+            value,
+        });
+    }
+    Ok(FlowAction {
+        name: action_name.into(),
+        params: Arc::new(params),
+    })
+}
+
+pub fn new_read_request(device_id: u64, entities: Vec<Entity>) -> ReadRequest {
+    ReadRequest {
+        device_id,
+        entities,
+    }
+}
+
+/// Decode the per-update errors of a failed write.
+/// Returns `None` if the status does not carry p4runtime errors in its details,
+/// otherwise one `p4.v1.Error` per update, in the order of the updates in the request.
+pub fn decode_write_errors(status: &tonic::Status) -> Option<Vec<crate::proto::p4runtime::Error>> {
+    let details = status.details();
+    if details.is_empty() {
+        return None;
+    }
+    let status = <crate::proto::rpc::Status as prost::Message>::decode(details).ok()?;
+    if status.details.is_empty() {
+        return None;
+    }
+    status
+        .details
+        .iter()
+        .map(|any| {
+            <crate::proto::p4runtime::Error as prost::Message>::decode(any.value.as_slice()).ok()
+        })
+        .collect()
+}
+
+/// The identity of a table entry on device: its table id, matches and priority.
+/// Entries built by [build_table_entry] have canonical values and no wildcard matches,
+/// so a flow and the same flow read back from the device have the same key.
+pub fn table_entry_key(table_entry: &TableEntry) -> u64 {
+    let mut matches: Vec<&FieldMatch> = table_entry.r#match.iter().collect();
+    matches.sort_by_key(|m| m.field_id);
+    let mut buffer = Vec::new();
+    for m in matches {
+        prost::Message::encode(m, &mut buffer).unwrap();
+    }
+    crate::util::hash((table_entry.table_id, buffer, table_entry.priority))
+}
 
 pub fn table_entry_to_entity(table_entry: TableEntry) -> Entity {
     Entity {
@@ -314,6 +591,19 @@ pub fn get_meter_id(pipeconf: &P4Info, name: &str) -> Option<u32> {
     get_meter(pipeconf, name).map(|table| table.preamble.as_ref().unwrap().id)
 }
 
+/// Get the unit of a meter or a direct meter,
+/// `Unit::Unspecified` if P4Info does not declare one.
+pub fn get_meter_unit(index: &P4InfoIndex, name: &str) -> Option<meter_spec::Unit> {
+    let spec = index
+        .meter(name)
+        .map(|meter| meter.spec.as_ref())
+        .or_else(|| index.direct_meter(name).map(|meter| meter.spec.as_ref()))?;
+    Some(
+        spec.and_then(|spec| meter_spec::Unit::from_i32(spec.unit))
+            .unwrap_or(meter_spec::Unit::Unspecified),
+    )
+}
+
 pub fn get_counter<'a>(pipeconf: &'a P4Info, name: &str) -> Option<&'a Counter> {
     pipeconf
         .counters
@@ -344,92 +634,277 @@ pub fn get_directcounter_id(pipeconf: &P4Info, name: &str) -> Option<u32> {
     get_directcounter(pipeconf, name).map(|table| table.preamble.as_ref().unwrap().id)
 }
 
+/// Decode one digest message into a map of member name to value, using the P4Info type info of the digest.
+/// Members of nested structs are named like `outer.inner`.
+/// A digest whose type is not a struct is decoded into a single member named after the digest.
+pub fn decode_digest_data(
+    p4info: &P4Info,
+    digest: &Digest,
+    data: &crate::proto::p4runtime::P4Data,
+) -> HashMap<String, Bytes> {
+    let mut members = HashMap::new();
+    if let Some(type_spec) = digest.type_spec.as_ref() {
+        let name = match type_spec.type_spec {
+            Some(p4_data_type_spec::TypeSpec::Struct(_)) => "",
+            _ => digest
+                .preamble
+                .as_ref()
+                .map(|pre| pre.name.as_str())
+                .unwrap_or_default(),
+        };
+        decode_p4data(p4info, type_spec, data, name, &mut members);
+    }
+    members
+}
+
+/// Decode a bitstring read from a device, such as a register cell,
+/// `None` if the data is not a bitstring or does not fit in `T`.
+pub fn decode_p4data_bitstring<T: Decode>(data: &crate::proto::p4runtime::P4Data) -> Option<T> {
+    match data.data.as_ref() {
+        Some(crate::proto::p4runtime::p4_data::Data::Bitstring(value)) => T::decode(value),
+        _ => None,
+    }
+}
+
+fn decode_p4data(
+    p4info: &P4Info,
+    type_spec: &P4DataTypeSpec,
+    data: &crate::proto::p4runtime::P4Data,
+    name: &str,
+    members: &mut HashMap<String, Bytes>,
+) {
+    use crate::proto::p4runtime::p4_data::Data;
+
+    match (type_spec.type_spec.as_ref(), data.data.as_ref()) {
+        (Some(p4_data_type_spec::TypeSpec::Struct(named)), Some(Data::Struct(value))) => {
+            let struct_spec = p4info
+                .type_info
+                .as_ref()
+                .and_then(|type_info| type_info.structs.get(&named.name));
+            let struct_spec = if let Some(spec) = struct_spec {
+                spec
+            } else {
+                warn!(target: "core", "struct type {} not found in P4Info", named.name);
+                return;
+            };
+            for (member, value) in struct_spec.members.iter().zip(value.members.iter()) {
+                if let Some(member_type) = member.type_spec.as_ref() {
+                    let member_name = if name.is_empty() {
+                        member.name.clone()
+                    } else {
+                        format!("{}.{}", name, member.name)
+                    };
+                    decode_p4data(p4info, member_type, value, &member_name, members);
+                }
+            }
+        }
+        (_, Some(Data::Bitstring(value))) => {
+            members.insert(name.to_owned(), value.clone());
+        }
+        (_, Some(Data::Bool(value))) => {
+            members.insert(name.to_owned(), Bytes::copy_from_slice(&[*value as u8]));
+        }
+        (_, Some(Data::EnumValue(value))) => {
+            members.insert(name.to_owned(), value.clone());
+        }
+        (_, other) => {
+            debug!(target: "core", "unsupported digest member {}: {:?}", name, other);
+        }
+    }
+}
+
+pub fn new_digest_ack_request(digest_id: u32, list_id: u64) -> StreamMessageRequest {
+    StreamMessageRequest {
+        update: Some(stream_message_request::Update::DigestAck(DigestListAck {
+            digest_id,
+            list_id,
+        })),
+    }
+}
+
 pub fn get_actions_id(pipeconf: &P4Info, action_name: &str) -> Option<u32> {
     get_action(pipeconf, action_name).map(|table| table.preamble.as_ref().unwrap().id)
 }
 
-pub fn get_match_field_pb(
-    pipeconf: &P4Info,
+/// Build the p4runtime match of a field, `None` if the value is a wildcard and the field must be omitted.
+/// An exact value is promoted to the match kind of the field, e.g. to a ternary with a full mask.
+/// Values of a field with a named type are translated with [Pipeconf::translate_to_sdn], masks are not.
+pub fn get_match_field_pb<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
     table_name: &str,
     match_field_name: &str,
     value: &InnerValue,
-) -> Option<FieldMatch> {
-    let p4info_match = get_match_field_by_name(pipeconf, table_name, match_field_name).unwrap();
-    let bitwidth = p4info_match.bitwidth;
-    let byte_len = (bitwidth as f32 / 8.0).ceil() as usize;
-    let x = p4info_match.r#match.as_ref().map(|x| {
-        match x {
-            match_field::Match::MatchType(x) => {
-                match (match_field::MatchType::from_i32(*x), value) {
-                    (Some(match_field::MatchType::Exact), InnerValue::EXACT(v)) => {
-                        //                assert_eq!(byte_len, v.len());
-                        let v = adjust_value(v.clone(), byte_len);
-                        field_match::FieldMatchType::Exact(
-                            crate::proto::p4runtime::field_match::Exact { value: v },
-                        )
-                    }
-                    (Some(match_field::MatchType::Lpm), InnerValue::LPM(v, l)) => {
-                        assert_eq!(byte_len, v.len());
-                        field_match::FieldMatchType::Lpm(
-                            crate::proto::p4runtime::field_match::Lpm {
-                                value: v.clone(),
-                                prefix_len: *l,
-                            },
-                        )
-                    }
-                    (Some(match_field::MatchType::Ternary), InnerValue::TERNARY(v, mask)) => {
-                        assert_eq!(byte_len, v.len());
-                        //                assert_eq!(byte_len, mask.len());
-                        let mask = adjust_value(mask.clone(), byte_len);
-                        field_match::FieldMatchType::Ternary(
-                            crate::proto::p4runtime::field_match::Ternary {
-                                value: v.clone(),
-                                mask,
-                            },
-                        )
-                    }
-                    (Some(match_field::MatchType::Range), InnerValue::RANGE(low, high)) => {
-                        assert_eq!(byte_len, low.len());
-                        assert_eq!(byte_len, high.len());
-                        field_match::FieldMatchType::Range(
-                            crate::proto::p4runtime::field_match::Range {
-                                low: low.clone(),
-                                high: high.clone(),
-                            },
-                        )
-                    }
-                    (Some(match_field::MatchType::Ternary), InnerValue::EXACT(v)) => {
-                        assert_eq!(byte_len, v.len());
-                        //                assert_eq!(byte_len, mask.len());
-                        let mask = adjust_value_with(
-                            Bytes::from_static(&[0xff, 0xff, 0xff, 0xff]),
-                            byte_len,
-                            0xff,
-                        );
-                        for i in 0..byte_len {
-                            assert!(mask.as_ref()[i] & v.as_ref()[i] == v.as_ref()[i]);
-                        }
-                        field_match::FieldMatchType::Ternary(
-                            crate::proto::p4runtime::field_match::Ternary {
-                                value: v.clone(),
-                                mask,
-                            },
-                        )
-                    }
-                    other => {
-                        dbg!(other);
-                        panic!("what")
-                    }
+) -> std::result::Result<Option<FieldMatch>, ValidationError> {
+    let index = pipeconf.get_p4info_index();
+    let p4info_match = index
+        .match_field(table_name, match_field_name)
+        .ok_or_else(|| ValidationError::UnknownName {
+            kind: "match field",
+            name: format!("{}.{}", table_name, match_field_name),
+        })?;
+    let name = p4info_match.name.as_str();
+    let kind = match p4info_match.r#match.as_ref() {
+        Some(match_field::Match::MatchType(kind)) => match_field::MatchType::from_i32(*kind),
+        _ => None,
+    };
+    let type_name = p4info_match.type_name.as_ref();
+    let bitwidth = sdn_bitwidth(index, type_name, p4info_match.bitwidth);
+    let translated;
+    let value = match type_name {
+        Some(type_name) => {
+            let translate = |v: &Bytes| translate_to_sdn(pipeconf, name, &type_name.name, v);
+            translated = match value {
+                InnerValue::EXACT(v) => InnerValue::EXACT(translate(v)?),
+                InnerValue::LPM(v, prefix_len) => InnerValue::LPM(translate(v)?, *prefix_len),
+                InnerValue::TERNARY(v, mask) => InnerValue::TERNARY(translate(v)?, mask.clone()),
+                InnerValue::RANGE(low, high) => {
+                    InnerValue::RANGE(translate(low)?, translate(high)?)
                 }
+                InnerValue::OPTIONAL(v) => InnerValue::OPTIONAL(translate(v)?),
+                InnerValue::DONT_CARE => InnerValue::DONT_CARE,
+            };
+            &translated
+        }
+        None => value,
+    };
+    // strings of a `sdn_string` type are sent as is, and only match exactly.
+    let is_string = is_sdn_string(index, type_name);
+    let mismatch = |kind: Option<match_field::MatchType>, value: &InnerValue| {
+        ValidationError::MatchKindMismatch {
+            field: name.to_owned(),
+            expected: kind
+                .map(|kind| format!("{:?}", kind))
+                .unwrap_or_else(|| "unsupported".to_owned()),
+            actual: value.kind(),
+        }
+    };
+    if is_string {
+        let field_match_type = match (kind, value) {
+            (Some(match_field::MatchType::Exact), InnerValue::EXACT(v)) => {
+                field_match::FieldMatchType::Exact(Exact { value: v.clone() })
             }
-            match_field::Match::OtherMatchType(_) => panic!("unsupported"),
+            (Some(match_field::MatchType::Optional), InnerValue::OPTIONAL(v))
+            | (Some(match_field::MatchType::Optional), InnerValue::EXACT(v)) => {
+                field_match::FieldMatchType::Optional(Optional { value: v.clone() })
+            }
+            (Some(match_field::MatchType::Optional), InnerValue::DONT_CARE) => return Ok(None),
+            (kind, value) => return Err(mismatch(kind, value)),
+        };
+        return Ok(Some(crate::proto::p4runtime::FieldMatch {
+            field_id: p4info_match.id,
+            field_match_type: Some(field_match_type),
+        }));
+    }
+    // values padded to the full width of the field, to apply masks.
+    let full_width = |value: &Bytes| {
+        decode_bytestring(value, bitwidth).ok_or_else(|| ValidationError::ValueTooWide {
+            name: name.to_owned(),
+            bitwidth,
+        })
+    };
+    let full_mask = prefix_mask(bitwidth, bitwidth);
+    let field_match_type = match (kind, value) {
+        (Some(match_field::MatchType::Exact), InnerValue::EXACT(v)) => {
+            field_match::FieldMatchType::Exact(Exact {
+                value: fit_value(name, v, bitwidth)?,
+            })
         }
-    });
-    let mut p4runtime_match = crate::proto::p4runtime::FieldMatch {
-        field_id: p4info_match.id,
-        field_match_type: x,
+        (Some(match_field::MatchType::Lpm), InnerValue::LPM(v, prefix_len)) => {
+            if *prefix_len < 0 || *prefix_len > bitwidth {
+                return Err(ValidationError::PrefixLengthOutOfRange {
+                    field: name.to_owned(),
+                    prefix_len: *prefix_len,
+                    bitwidth,
+                });
+            }
+            if *prefix_len == 0 {
+                return Ok(None);
+            }
+            // bits after the prefix must be zero.
+            let value = mask_value(&full_width(v)?, &prefix_mask(bitwidth, *prefix_len));
+            field_match::FieldMatchType::Lpm(Lpm {
+                value: fit_value(name, &value, bitwidth)?,
+                prefix_len: *prefix_len,
+            })
+        }
+        (Some(match_field::MatchType::Lpm), InnerValue::EXACT(v)) => {
+            field_match::FieldMatchType::Lpm(Lpm {
+                value: fit_value(name, v, bitwidth)?,
+                prefix_len: bitwidth,
+            })
+        }
+        (Some(match_field::MatchType::Ternary), InnerValue::TERNARY(v, mask)) => {
+            let mask = full_width(mask)?;
+            if mask.iter().all(|b| *b == 0) {
+                return Ok(None);
+            }
+            // bits outside the mask must be zero.
+            let value = mask_value(&full_width(v)?, &mask);
+            field_match::FieldMatchType::Ternary(Ternary {
+                value: fit_value(name, &value, bitwidth)?,
+                mask: fit_value(name, &mask, bitwidth)?,
+            })
+        }
+        (Some(match_field::MatchType::Ternary), InnerValue::EXACT(v))
+        | (Some(match_field::MatchType::Ternary), InnerValue::OPTIONAL(v)) => {
+            field_match::FieldMatchType::Ternary(Ternary {
+                value: fit_value(name, v, bitwidth)?,
+                mask: fit_value(name, &full_mask, bitwidth)?,
+            })
+        }
+        (Some(match_field::MatchType::Range), InnerValue::RANGE(low, high)) => {
+            let (low, high) = (full_width(low)?, full_width(high)?);
+            // both bounds have the full width, so they compare as numbers.
+            if low > high {
+                return Err(ValidationError::InvalidRange {
+                    field: name.to_owned(),
+                });
+            }
+            if low.iter().all(|b| *b == 0) && high == full_mask {
+                return Ok(None);
+            }
+            field_match::FieldMatchType::Range(Range {
+                low: fit_value(name, &low, bitwidth)?,
+                high: fit_value(name, &high, bitwidth)?,
+            })
+        }
+        (Some(match_field::MatchType::Range), InnerValue::EXACT(v)) => {
+            let value = fit_value(name, v, bitwidth)?;
+            field_match::FieldMatchType::Range(Range {
+                low: value.clone(),
+                high: value,
+            })
+        }
+        (Some(match_field::MatchType::Optional), InnerValue::OPTIONAL(v))
+        | (Some(match_field::MatchType::Optional), InnerValue::EXACT(v)) => {
+            field_match::FieldMatchType::Optional(Optional {
+                value: fit_value(name, v, bitwidth)?,
+            })
+        }
+        (Some(kind), InnerValue::DONT_CARE) if kind != match_field::MatchType::Exact => {
+            return Ok(None);
+        }
+        (kind, value) => return Err(mismatch(kind, value)),
     };
-    return Some(p4runtime_match);
+    Ok(Some(crate::proto::p4runtime::FieldMatch {
+        field_id: p4info_match.id,
+        field_match_type: Some(field_match_type),
+    }))
+}
+
+/// A mask of the full byte width of a field with the first `prefix_len` bits of the field set.
+fn prefix_mask(bitwidth: i32, prefix_len: i32) -> Bytes {
+    let bytes_len = (bitwidth.max(0) as usize + 7) / 8;
+    let padding = bytes_len * 8 - bitwidth.max(0) as usize;
+    let mut mask = vec![0u8; bytes_len];
+    for bit in padding..padding + prefix_len.max(0) as usize {
+        mask[bit / 8] |= 0x80 >> (bit % 8);
+    }
+    Bytes::from(mask)
+}
+
+fn mask_value(value: &[u8], mask: &[u8]) -> Bytes {
+    value.iter().zip(mask.iter()).map(|(v, m)| v & m).collect()
 }
 
 pub fn get_action_param_by_name<'a>(
@@ -447,6 +922,70 @@ pub fn get_action_param_by_name<'a>(
     })
 }
 
+/// Build the metadata of a packet-out from named values.
+/// Each value is big-endian and is encoded as the canonical bytestring of its field in the `packet_out` header.
+/// Values of a translated type are translated with [Pipeconf::translate_to_sdn].
+pub fn build_packet_out_metadata<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    metadata: &[(&str, Bytes)],
+) -> Result<Vec<PacketMetadata>> {
+    let index = pipeconf.get_p4info_index();
+    if index.controller_packet_metadata("packet_out").is_none() {
+        return Err(ValidationError::UnknownName {
+            kind: "controller packet metadata",
+            name: "packet_out".to_owned(),
+        }
+        .into());
+    }
+    metadata
+        .iter()
+        .map(|(name, value)| {
+            let field = index.packet_metadata("packet_out", name).ok_or_else(|| {
+                ValidationError::UnknownName {
+                    kind: "packet_out metadata",
+                    name: (*name).to_owned(),
+                }
+            })?;
+            Ok(PacketMetadata {
+                metadata_id: field.id,
+                value: encode_typed_value(
+                    pipeconf,
+                    name,
+                    field.type_name.as_ref(),
+                    field.bitwidth,
+                    value,
+                )?,
+            })
+        })
+        .collect()
+}
+
+/// Decode the metadata of a packet-in into a map from the field name in the `packet_in` header to its value,
+/// padded to the full width of the field. Metadata with an id unknown to P4Info is skipped.
+pub fn decode_packet_in_metadata<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
+    metadata: &[PacketMetadata],
+) -> HashMap<String, Bytes> {
+    let index = pipeconf.get_p4info_index();
+    metadata
+        .iter()
+        .filter_map(|meta| {
+            index
+                .packet_metadata_by_id("packet_in", meta.metadata_id)
+                .map(|field| {
+                    let value = decode_typed_value(
+                        pipeconf,
+                        field.type_name.as_ref(),
+                        field.bitwidth,
+                        &meta.value,
+                    )
+                    .unwrap_or_else(|| meta.value.clone());
+                    (field.name.clone(), value)
+                })
+        })
+        .collect()
+}
+
 pub fn get_packout_egress_port_metaid(p4info: &P4Info) -> Option<u32> {
     p4info
         .controller_packet_metadata
@@ -479,62 +1018,140 @@ pub fn get_packin_egress_port_metaid(p4info: &P4Info) -> Option<u32> {
         })
 }
 
-pub fn get_action_param_pb(
-    pipeconf: &P4Info,
+pub fn get_action_param_pb<P: Pipeconf + ?Sized>(
+    pipeconf: &P,
     action_name: &str,
     param_name: &str,
-    mut value: InnerParamValue,
-) -> crate::proto::p4runtime::action::Param {
-    let p4info_param = get_action_param_by_name(pipeconf, action_name, param_name).unwrap();
-    let bytes_len = (p4info_param.bitwidth as f32 / 8.0).ceil() as usize;
-    //        println!("adjust value: action:{}, param:{}, value:{:?}, bitwidth:{}",action_name,param_name,value,p4info_param.bitwidth);
-    let value = adjust_value(value, bytes_len);
-    let p4runtime_param = crate::proto::p4runtime::action::Param {
+    value: InnerParamValue,
+) -> std::result::Result<crate::proto::p4runtime::action::Param, ValidationError> {
+    let p4info_param = pipeconf
+        .get_p4info_index()
+        .action_param(action_name, param_name)
+        .ok_or_else(|| ValidationError::UnknownName {
+            kind: "action param",
+            name: format!("{}.{}", action_name, param_name),
+        })?;
+    Ok(crate::proto::p4runtime::action::Param {
         param_id: p4info_param.id,
-        value,
-    };
-    return p4runtime_param;
+        value: encode_typed_value(
+            pipeconf,
+            param_name,
+            p4info_param.type_name.as_ref(),
+            p4info_param.bitwidth,
+            &value,
+        )?,
+    })
 }
 
-pub async fn new_set_forwarding_pipeline_config_request(
-    p4info: &P4Info,
-    bmv2_json_file_path: &Path,
-    master_arbitration: &MasterArbitrationUpdate,
-    device_id: u64,
-) -> Result<crate::proto::p4runtime::SetForwardingPipelineConfigRequest> {
-    let mut file = tokio::fs::File::open(bmv2_json_file_path)
-        .await
-        .map_err(|e| crate::error::DeviceError::DeviceConfigFileError {
-            path: bmv2_json_file_path.display().to_string(),
+/// Read the device config of a pipeline, e.g. the bmv2 json file.
+pub async fn read_device_config(path: &Path) -> Result<Bytes> {
+    let mut file = tokio::fs::File::open(path).await.map_err(|e| {
+        crate::error::DeviceError::DeviceConfigFileError {
+            path: path.display().to_string(),
             error: e,
-        })?;
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer).await.map_err(|e| {
+        }
+    })?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer).await.map_err(|e| {
         crate::error::DeviceError::DeviceConfigFileError {
-            path: bmv2_json_file_path.display().to_string(),
+            path: path.display().to_string(),
             error: e,
         }
     })?;
+    Ok(buffer.into())
+}
+
+/// The cookie identifying a pipeline config, derived from the P4Info and the device config.
+/// It is the same across controller restarts and Rust releases, so a running device can be adopted if its cookie matches.
+pub fn pipeline_config_cookie(p4info: &P4Info, device_config: &[u8]) -> u64 {
+    let mut p4info = p4info.clone();
+    let type_info = p4info.type_info.take();
+    let mut buffer = Vec::new();
+    prost::Message::encode(&p4info, &mut buffer).unwrap();
+    // maps are encoded in the iteration order of HashMap, which differs between runs,
+    // so encode the entries sorted by name instead.
+    if let Some(type_info) = type_info {
+        encode_sorted(&type_info.structs, &mut buffer);
+        encode_sorted(&type_info.headers, &mut buffer);
+        encode_sorted(&type_info.header_unions, &mut buffer);
+        encode_sorted(&type_info.enums, &mut buffer);
+        encode_sorted(&type_info.serializable_enums, &mut buffer);
+        encode_sorted(&type_info.new_types, &mut buffer);
+        if let Some(error) = type_info.error {
+            prost::Message::encode(&error, &mut buffer).unwrap();
+        }
+    }
+    fnv1a(fnv1a(FNV_OFFSET_BASIS, &buffer), device_config)
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64-bit FNV-1a, a fixed hash unlike `DefaultHasher`, whose algorithm may change between Rust releases.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn encode_sorted<M: prost::Message>(map: &HashMap<String, M>, buffer: &mut Vec<u8>) {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, value) in entries {
+        buffer.extend_from_slice(name.as_bytes());
+        prost::Message::encode(value, buffer).unwrap();
+    }
+}
+
+pub fn new_set_forwarding_pipeline_config_request(
+    p4info: &P4Info,
+    device_config: Bytes,
+    cookie: u64,
+    master_arbitration: &MasterArbitrationUpdate,
+    device_id: u64,
+    action: crate::proto::p4runtime::set_forwarding_pipeline_config_request::Action,
+) -> crate::proto::p4runtime::SetForwardingPipelineConfigRequest {
     let election_id = master_arbitration.election_id.clone();
-    Ok(crate::proto::p4runtime::SetForwardingPipelineConfigRequest {
+    let role_id = master_arbitration
+        .role
+        .as_ref()
+        .map(|role| role.id)
+        .unwrap_or_default();
+    crate::proto::p4runtime::SetForwardingPipelineConfigRequest {
         device_id,
-        role_id: 0,
+        role_id,
         election_id,
-        action: crate::proto::p4runtime::set_forwarding_pipeline_config_request::Action::VerifyAndCommit.into(),
+        action: action.into(),
         config: Some(crate::proto::p4runtime::ForwardingPipelineConfig {
             p4info: Some(p4info.clone()),
-            p4_device_config: buffer.into(),
-            cookie: None,
+            p4_device_config: device_config,
+            cookie: Some(forwarding_pipeline_config::Cookie { cookie }),
         }),
-    })
+    }
 }
 
-pub fn new_master_update_request(device_id: u64, option: (u64, u64)) -> StreamMessageRequest {
+pub fn new_get_pipeline_config_cookie_request(
+    device_id: u64,
+) -> crate::proto::p4runtime::GetForwardingPipelineConfigRequest {
+    crate::proto::p4runtime::GetForwardingPipelineConfigRequest {
+        device_id,
+        response_type:
+            crate::proto::p4runtime::get_forwarding_pipeline_config_request::ResponseType::CookieOnly
+                as i32,
+    }
+}
+
+pub fn new_master_update_request(
+    device_id: u64,
+    option: (u64, u64),
+    role: Option<Role>,
+) -> StreamMessageRequest {
     StreamMessageRequest {
         update: Some(stream_message_request::Update::Arbitration(
             MasterArbitrationUpdate {
                 device_id,
-                role: None,
+                role,
                 election_id: Uint128 {
                     high: option.1,
                     low: option.0,
@@ -572,3 +1189,979 @@ pub fn new_stratum_get_interface_mac(name: &str) -> rusty_p4_proto::proto::gnmi:
         extension: vec![],
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::value::{EXACT, LPM, OPTIONAL, RANGE, TERNARY};
+    use smallvec::smallvec;
+
+    fn preamble(id: u32, name: &str) -> Option<Preamble> {
+        Some(Preamble {
+            id,
+            name: name.to_owned(),
+            ..Default::default()
+        })
+    }
+
+    fn match_field(id: u32, name: &str, bitwidth: i32, kind: match_field::MatchType) -> MatchField {
+        MatchField {
+            id,
+            name: name.to_owned(),
+            bitwidth,
+            r#match: Some(match_field::Match::MatchType(kind as i32)),
+            ..Default::default()
+        }
+    }
+
+    fn test_p4info() -> P4Info {
+        P4Info {
+            tables: vec![Table {
+                preamble: preamble(1, "MyIngress.ipv4_lpm"),
+                match_fields: vec![
+                    match_field(1, "hdr.ipv4.dstAddr", 32, match_field::MatchType::Lpm),
+                    match_field(
+                        2,
+                        "standard_metadata.ingress_port",
+                        9,
+                        match_field::MatchType::Exact,
+                    ),
+                ],
+                ..Default::default()
+            }],
+            actions: vec![Action {
+                preamble: preamble(2, "MyIngress.ipv4_forward"),
+                params: vec![action::Param {
+                    id: 1,
+                    name: "port".to_owned(),
+                    bitwidth: 9,
+                    ..Default::default()
+                }],
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn packet_metadata_p4info() -> P4Info {
+        let field = |id, name: &str, bitwidth| controller_packet_metadata::Metadata {
+            id,
+            name: name.to_owned(),
+            bitwidth,
+            ..Default::default()
+        };
+        P4Info {
+            controller_packet_metadata: vec![
+                ControllerPacketMetadata {
+                    preamble: preamble(20, "packet_in"),
+                    metadata: vec![field(1, "ingress_port", 9), field(2, "reason", 7)],
+                },
+                ControllerPacketMetadata {
+                    preamble: preamble(21, "packet_out"),
+                    metadata: vec![
+                        field(1, "egress_port", 9),
+                        field(2, "skip_pipeline", 1),
+                        field(3, "queue_id", 20),
+                    ],
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_packet_out_metadata() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", packet_metadata_p4info(), "");
+        let metadata = build_packet_out_metadata(
+            &pipeconf,
+            &[
+                ("egress_port", Bytes::from_static(&[0, 0, 1, 2])),
+                ("skip_pipeline", Bytes::from_static(&[1])),
+                ("queue_id", Bytes::from_static(&[5])),
+            ],
+        )
+        .unwrap();
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata[0].metadata_id, 1);
+        assert_eq!(metadata[0].value.as_ref(), &[1, 2]);
+        assert_eq!(metadata[1].value.as_ref(), &[1]);
+        assert_eq!(metadata[2].metadata_id, 3);
+        assert_eq!(metadata[2].value.as_ref(), &[5]);
+
+        let unknown =
+            build_packet_out_metadata(&pipeconf, &[("priority", Bytes::from_static(&[1]))]);
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn test_decode_packet_in_metadata() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", packet_metadata_p4info(), "");
+        let metadata = vec![
+            PacketMetadata {
+                metadata_id: 1,
+                value: Bytes::from_static(&[1, 2]),
+            },
+            PacketMetadata {
+                metadata_id: 2,
+                value: Bytes::from_static(&[3]),
+            },
+            PacketMetadata {
+                metadata_id: 9,
+                value: Bytes::from_static(&[4]),
+            },
+        ];
+        let decoded = decode_packet_in_metadata(&pipeconf, &metadata);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded["ingress_port"].as_ref(), &[1, 2]);
+        assert_eq!(decoded["reason"].as_ref(), &[3]);
+
+        // the same field id in `packet_out` is another field.
+        let index = pipeconf.get_p4info_index();
+        assert_eq!(
+            index
+                .packet_metadata_by_id("packet_out", 1)
+                .map(|field| (field.name.as_str(), field.bitwidth)),
+            Some(("egress_port", 9))
+        );
+        assert_eq!(
+            index
+                .packet_metadata("packet_in", "reason")
+                .map(|field| field.id),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_get_meter_unit() {
+        let p4info = P4Info {
+            meters: vec![Meter {
+                preamble: preamble(10, "MyIngress.tenant_meter"),
+                spec: Some(MeterSpec {
+                    unit: meter_spec::Unit::Bytes as i32,
+                }),
+                size: 16,
+                ..Default::default()
+            }],
+            direct_meters: vec![DirectMeter {
+                preamble: preamble(11, "MyIngress.flow_meter"),
+                spec: Some(MeterSpec {
+                    unit: meter_spec::Unit::Packets as i32,
+                }),
+                direct_table_id: 1,
+            }],
+            ..Default::default()
+        };
+        let index = P4InfoIndex::new(p4info);
+        assert_eq!(
+            get_meter_unit(&index, "MyIngress.tenant_meter"),
+            Some(meter_spec::Unit::Bytes)
+        );
+        assert_eq!(
+            get_meter_unit(&index, "MyIngress.flow_meter"),
+            Some(meter_spec::Unit::Packets)
+        );
+        assert_eq!(get_meter_unit(&index, "MyIngress.missing"), None);
+    }
+
+    #[test]
+    fn test_p4info_index() {
+        let mut p4info = test_p4info();
+        p4info.tables[0].preamble.as_mut().unwrap().alias = "ipv4_lpm".to_owned();
+        let index = P4InfoIndex::new(p4info);
+        assert_eq!(index.table_id("MyIngress.ipv4_lpm"), Some(1));
+        assert_eq!(index.table_id("ipv4_lpm"), Some(1));
+        assert_eq!(index.table_id("MyIngress.missing"), None);
+        assert_eq!(
+            index
+                .match_field("ipv4_lpm", "hdr.ipv4.dstAddr")
+                .map(|x| x.bitwidth),
+            Some(32)
+        );
+        assert_eq!(
+            index.match_field_by_id(1, 2).map(|x| x.name.as_str()),
+            Some("standard_metadata.ingress_port")
+        );
+        assert_eq!(index.match_field_by_id(2, 2), None);
+        assert_eq!(index.action_id("MyIngress.ipv4_forward"), Some(2));
+        assert_eq!(
+            index
+                .action_param("MyIngress.ipv4_forward", "port")
+                .map(|x| x.bitwidth),
+            Some(9)
+        );
+        assert_eq!(
+            index.action_param_by_id(2, 1).map(|x| x.name.as_str()),
+            Some("port")
+        );
+    }
+
+    #[test]
+    fn test_table_entry_to_flow() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        let matches: SmallVec<[FlowMatch; 3]> = smallvec![
+            FlowMatch {
+                name: "hdr.ipv4.dstAddr".into(),
+                value: LPM(std::net::Ipv4Addr::new(10, 0, 0, 0), 8),
+            },
+            FlowMatch {
+                name: "standard_metadata.ingress_port".into(),
+                value: EXACT(1u16),
+            },
+        ];
+        let params: SmallVec<[FlowActionParam; 3]> = smallvec![FlowActionParam {
+            name: "port".into(),
+            value: Bytes::from_static(&[0, 2]),
+        }];
+        let action = FlowTableAction::Action(FlowAction {
+            name: "MyIngress.ipv4_forward".into(),
+            params: Arc::new(params.clone()),
+        });
+        let entry = build_table_entry(
+            &pipeconf,
+            "MyIngress.ipv4_lpm",
+            matches.as_ref(),
+            false,
+            &action,
+            1,
+            42,
+            1_000_000_000,
+        )
+        .unwrap();
+
+        let flow = table_entry_to_flow(&pipeconf, &entry).unwrap();
+        assert_eq!(flow.table.name, "MyIngress.ipv4_lpm");
+        assert_eq!(flow.table.matches.as_ref(), &matches);
+        match flow.action {
+            FlowTableAction::Action(action) => {
+                assert_eq!(action.name, "MyIngress.ipv4_forward");
+                assert_eq!(action.params[0].name, "port");
+                assert_eq!(action.params[0].value, params[0].value);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+        assert_eq!(flow.priority, 1);
+        assert_eq!(flow.metadata, 42);
+        assert_eq!(flow.idle_timeout_ns, 1_000_000_000);
+    }
+
+    #[test]
+    fn test_flow_with_owned_names() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        // names built at runtime, e.g. from a northbound request.
+        let pipe = String::from("MyIngress");
+        let flow = Flow {
+            table: FlowTable::new(
+                format!("{}.ipv4_lpm", pipe),
+                Arc::new(smallvec![FlowMatch::new(
+                    String::from("hdr.ipv4.dstAddr"),
+                    LPM(std::net::Ipv4Addr::new(10, 0, 0, 0), 8),
+                )]),
+            ),
+            action: FlowAction::new(
+                format!("{}.ipv4_forward", pipe),
+                Arc::new(smallvec![FlowActionParam::new(
+                    String::from("port"),
+                    Bytes::from_static(&[2]),
+                )]),
+            )
+            .into(),
+            priority: 1,
+            metadata: 0,
+            idle_timeout_ns: 0,
+        };
+        let entry = flow.to_table_entry(&pipeconf, 0).unwrap();
+        assert_eq!(entry.table_id, 1);
+
+        let read = Flow::from_table_entry(&pipeconf, &entry).unwrap();
+        assert_eq!(read.table, flow.table);
+    }
+
+    #[test]
+    fn test_table_entry_key() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        let action = FlowTableAction::Action(FlowAction {
+            name: "MyIngress.ipv4_forward".into(),
+            params: Arc::new(smallvec![FlowActionParam {
+                name: "port".into(),
+                value: Bytes::from_static(&[2]),
+            }]),
+        });
+        // unsorted matches with short values, as written by `flow!`.
+        let matches = [
+            FlowMatch {
+                name: "standard_metadata.ingress_port".into(),
+                value: EXACT(1u8),
+            },
+            FlowMatch {
+                name: "hdr.ipv4.dstAddr".into(),
+                value: LPM(std::net::Ipv4Addr::new(10, 0, 0, 0), 8),
+            },
+        ];
+        let build = |matches: &[FlowMatch], priority| {
+            build_table_entry(
+                &pipeconf,
+                "MyIngress.ipv4_lpm",
+                matches,
+                false,
+                &action,
+                priority,
+                0,
+                0,
+            )
+            .unwrap()
+        };
+        let entry = build(&matches, 1);
+        let read = table_entry_to_flow(&pipeconf, &entry).unwrap();
+        let rebuilt = read.to_table_entry(&pipeconf, 7).unwrap();
+        assert_eq!(table_entry_key(&entry), table_entry_key(&rebuilt));
+        assert_ne!(
+            table_entry_key(&entry),
+            table_entry_key(&build(&matches, 2))
+        );
+    }
+
+    #[test]
+    fn test_flow_json() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        let flow: Flow = serde_json::from_str(
+            r#"{
+                "table": {
+                    "name": "MyIngress.ipv4_lpm",
+                    "matches": [
+                        {"name": "standard_metadata.ingress_port", "value": {"exact": 1}},
+                        {"name": "hdr.ipv4.dstAddr", "value": {"lpm": "10.0.0.0/8"}}
+                    ]
+                },
+                "action": {
+                    "action": {
+                        "name": "MyIngress.ipv4_forward",
+                        "params": [{"name": "port", "value": "0x0002"}]
+                    }
+                },
+                "priority": 1
+            }"#,
+        )
+        .unwrap();
+        // matches are sorted by name, as in the `flow!` macro.
+        let names: Vec<&str> = flow.table.matches.iter().map(|m| m.name.as_ref()).collect();
+        assert_eq!(
+            names,
+            vec!["hdr.ipv4.dstAddr", "standard_metadata.ingress_port"]
+        );
+        let entry = flow.to_table_entry(&pipeconf, 0).unwrap();
+        assert_eq!(
+            entry.r#match[0].field_match_type,
+            Some(FieldMatchType::Lpm(Lpm {
+                value: Bytes::from_static(&[10, 0, 0, 0]),
+                prefix_len: 8
+            }))
+        );
+
+        // flows read back have values of the full field width, written as addresses or hex.
+        let read = Flow::from_table_entry(&pipeconf, &entry).unwrap();
+        let json = serde_json::to_string(&read).unwrap();
+        assert_eq!(
+            json,
+            r#"{"table":{"name":"MyIngress.ipv4_lpm","matches":[{"name":"hdr.ipv4.dstAddr","value":{"lpm":"10.0.0.0/8"}},{"name":"standard_metadata.ingress_port","value":{"exact":"0x0001"}}]},"action":{"action":{"name":"MyIngress.ipv4_forward","params":[{"name":"port","value":"0x0002"}]}},"priority":1,"metadata":0,"idle_timeout_ns":0}"#
+        );
+        let parsed: Flow = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.table, read.table);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+
+    #[test]
+    fn test_table_entry_to_flow_action_set() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        let matches: SmallVec<[FlowMatch; 3]> = smallvec![FlowMatch {
+            name: "hdr.ipv4.dstAddr".into(),
+            value: LPM(std::net::Ipv4Addr::new(10, 0, 0, 0), 8),
+        }];
+        let weighted_action = |port: u8, weight| FlowWeightedAction {
+            action: FlowAction {
+                name: "MyIngress.ipv4_forward".into(),
+                params: Arc::new(smallvec![FlowActionParam {
+                    name: "port".into(),
+                    value: Bytes::copy_from_slice(&[0, port]),
+                }]),
+            },
+            weight,
+            watch: 0,
+        };
+        let action = FlowTableAction::ActionSet(Arc::new(smallvec![
+            weighted_action(1, 1),
+            weighted_action(2, 3)
+        ]));
+        let entry = build_table_entry(
+            &pipeconf,
+            "MyIngress.ipv4_lpm",
+            matches.as_ref(),
+            false,
+            &action,
+            1,
+            0,
+            0,
+        )
+        .unwrap();
+
+        let flow = table_entry_to_flow(&pipeconf, &entry).unwrap();
+        match flow.action {
+            FlowTableAction::ActionSet(actions) => {
+                assert_eq!(actions.len(), 2);
+                assert_eq!(actions[1].action.name, "MyIngress.ipv4_forward");
+                assert_eq!(actions[1].action.params[0].value.as_ref(), &[0, 2]);
+                assert_eq!(actions[1].weight, 3);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_build_table_entry_match() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        let matches = [FlowMatch {
+            name: "hdr.ipv4.dstAddr".into(),
+            value: LPM(std::net::Ipv4Addr::new(10, 0, 0, 0), 8),
+        }];
+        let entry = build_table_entry_match(&pipeconf, "MyIngress.ipv4_lpm", &matches, 0).unwrap();
+        assert_eq!(
+            entry,
+            TableEntry {
+                table_id: 1,
+                r#match: vec![FieldMatch {
+                    field_id: 1,
+                    field_match_type: Some(FieldMatchType::Lpm(Lpm {
+                        value: Bytes::from_static(&[10, 0, 0, 0]),
+                        prefix_len: 8
+                    })),
+                }],
+                ..Default::default()
+            }
+        );
+        assert!(matches!(
+            build_table_entry_match(&pipeconf, "MyIngress.missing", &matches, 0),
+            Err(ValidationError::UnknownName { kind: "table", .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_table_entry_validation() {
+        let mut p4info = test_p4info();
+        p4info.tables.push(Table {
+            preamble: preamble(3, "MyIngress.acl"),
+            match_fields: vec![match_field(
+                1,
+                "hdr.ethernet.etherType",
+                16,
+                match_field::MatchType::Ternary,
+            )],
+            ..Default::default()
+        });
+        let pipeconf = DefaultPipeconf::from_p4info("test", p4info, "");
+        let forward = |port: Bytes| {
+            FlowTableAction::Action(FlowAction {
+                name: "MyIngress.ipv4_forward".into(),
+                params: Arc::new(smallvec![FlowActionParam {
+                    name: "port".into(),
+                    value: port
+                }]),
+            })
+        };
+        let build = |table: &str, matches: &[FlowMatch], action: &FlowTableAction, priority| {
+            build_table_entry(&pipeconf, table, matches, false, action, priority, 0, 0)
+        };
+        let lpm = |prefix_len| FlowMatch {
+            name: "hdr.ipv4.dstAddr".into(),
+            value: LPM(std::net::Ipv4Addr::new(10, 0, 0, 0), prefix_len),
+        };
+        let port = forward(Bytes::from_static(&[1]));
+
+        let entry = build("MyIngress.ipv4_lpm", &[lpm(8)], &port, 0).unwrap();
+        // values are canonical, and priority is not required without ternary or range fields.
+        assert_eq!(
+            entry.r#match[0].field_match_type,
+            Some(FieldMatchType::Lpm(Lpm {
+                value: Bytes::from_static(&[10, 0, 0, 0]),
+                prefix_len: 8
+            }))
+        );
+        match entry.action.and_then(|action| action.r#type) {
+            Some(crate::proto::p4runtime::table_action::Type::Action(action)) => {
+                assert_eq!(action.params[0].value.as_ref(), &[1]);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+
+        assert_eq!(
+            build("MyIngress.missing", &[], &port, 1),
+            Err(ValidationError::UnknownName {
+                kind: "table",
+                name: "MyIngress.missing".to_owned()
+            })
+        );
+        let unknown_action = FlowTableAction::Action(FlowAction {
+            name: "MyIngress.drop".into(),
+            params: Default::default(),
+        });
+        assert!(matches!(
+            build("MyIngress.ipv4_lpm", &[], &unknown_action, 1),
+            Err(ValidationError::UnknownName { kind: "action", .. })
+        ));
+        assert!(matches!(
+            build("MyIngress.ipv4_lpm", &[lpm(33)], &port, 1),
+            Err(ValidationError::PrefixLengthOutOfRange { prefix_len: 33, .. })
+        ));
+        assert!(matches!(
+            build(
+                "MyIngress.ipv4_lpm",
+                &[lpm(8)],
+                &forward(Bytes::from_static(&[2, 0])),
+                1
+            ),
+            Err(ValidationError::ValueTooWide { bitwidth: 9, .. })
+        ));
+        let range = FlowMatch {
+            name: "hdr.ipv4.dstAddr".into(),
+            value: RANGE(1u32, 2u32),
+        };
+        assert!(matches!(
+            build("MyIngress.ipv4_lpm", &[range], &port, 1),
+            Err(ValidationError::MatchKindMismatch {
+                actual: "Range",
+                ..
+            })
+        ));
+        let ether_type = FlowMatch {
+            name: "hdr.ethernet.etherType".into(),
+            value: EXACT(0x0800u16),
+        };
+        assert_eq!(
+            build("MyIngress.acl", &[ether_type.clone()], &port, 0),
+            Err(ValidationError::PriorityRequired {
+                table: "MyIngress.acl".to_owned()
+            })
+        );
+        assert!(build("MyIngress.acl", &[ether_type], &port, 10).is_ok());
+    }
+
+    #[test]
+    fn test_get_match_field_pb_match_kinds() {
+        let p4info = P4Info {
+            tables: vec![Table {
+                preamble: preamble(4, "MyIngress.kinds"),
+                match_fields: vec![
+                    match_field(1, "lpm", 32, match_field::MatchType::Lpm),
+                    match_field(2, "ternary", 12, match_field::MatchType::Ternary),
+                    match_field(3, "range", 16, match_field::MatchType::Range),
+                    match_field(4, "optional", 9, match_field::MatchType::Optional),
+                    match_field(5, "exact", 9, match_field::MatchType::Exact),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let pipeconf = DefaultPipeconf::from_p4info("test", p4info, "");
+        let build = |field: &str, value: InnerValue| {
+            get_match_field_pb(&pipeconf, "MyIngress.kinds", field, &value)
+                .map(|m| m.and_then(|m| m.field_match_type))
+        };
+        let bytes = |b: &'static [u8]| Bytes::from_static(b);
+
+        // bits after the prefix are cleared, and a zero prefix is a wildcard.
+        assert_eq!(
+            build("lpm", LPM(std::net::Ipv4Addr::new(10, 1, 2, 3), 16)),
+            Ok(Some(FieldMatchType::Lpm(Lpm {
+                value: bytes(&[10, 1, 0, 0]),
+                prefix_len: 16
+            })))
+        );
+        assert_eq!(build("lpm", LPM(0u32, 0)), Ok(None));
+        assert_eq!(
+            build("lpm", EXACT(1u32)),
+            Ok(Some(FieldMatchType::Lpm(Lpm {
+                value: bytes(&[1]),
+                prefix_len: 32
+            })))
+        );
+
+        // the exact mask is sized from the bitwidth, and a zero mask is a wildcard.
+        assert_eq!(
+            build("ternary", EXACT(0x0123u16)),
+            Ok(Some(FieldMatchType::Ternary(Ternary {
+                value: bytes(&[1, 0x23]),
+                mask: bytes(&[0x0f, 0xff])
+            })))
+        );
+        assert_eq!(
+            build("ternary", TERNARY(0x0123u16, 0x0f0u16)),
+            Ok(Some(FieldMatchType::Ternary(Ternary {
+                value: bytes(&[0x20]),
+                mask: bytes(&[0xf0])
+            })))
+        );
+        assert_eq!(build("ternary", TERNARY(0x0123u16, 0u16)), Ok(None));
+
+        assert_eq!(
+            build("range", EXACT(5u16)),
+            Ok(Some(FieldMatchType::Range(Range {
+                low: bytes(&[5]),
+                high: bytes(&[5])
+            })))
+        );
+        assert_eq!(build("range", RANGE(0u16, 0xffffu16)), Ok(None));
+        assert_eq!(
+            build("range", RANGE(0x0100u16, 0x00ffu16)),
+            Err(ValidationError::InvalidRange {
+                field: "range".to_owned()
+            })
+        );
+
+        assert_eq!(
+            build("optional", OPTIONAL(3u16)),
+            Ok(Some(FieldMatchType::Optional(Optional {
+                value: bytes(&[3])
+            })))
+        );
+        assert_eq!(build("optional", InnerValue::DONT_CARE), Ok(None));
+        assert!(matches!(
+            build("exact", InnerValue::DONT_CARE),
+            Err(ValidationError::MatchKindMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_value_translation() {
+        let named = |name: &str| {
+            Some(P4NamedType {
+                name: name.to_owned(),
+            })
+        };
+        let member = |name: &str, value: &'static [u8]| p4_serializable_enum_type_spec::Member {
+            name: name.to_owned(),
+            value: Bytes::from_static(value),
+            ..Default::default()
+        };
+        let mut type_info = P4TypeInfo::default();
+        type_info.new_types.insert(
+            "port_id_t".to_owned(),
+            P4NewTypeSpec {
+                representation: Some(p4_new_type_spec::Representation::TranslatedType(
+                    P4NewTypeTranslation {
+                        uri: "tna/PortId_t".to_owned(),
+                        sdn_type: Some(p4_new_type_translation::SdnType::SdnBitwidth(32)),
+                    },
+                )),
+                ..Default::default()
+            },
+        );
+        type_info.serializable_enums.insert(
+            "color_t".to_owned(),
+            P4SerializableEnumTypeSpec {
+                underlying_type: Some(P4BitTypeSpec { bitwidth: 8 }),
+                members: vec![member("RED", &[1]), member("GREEN", &[2])],
+                ..Default::default()
+            },
+        );
+        let p4info = P4Info {
+            tables: vec![Table {
+                preamble: preamble(5, "MyIngress.ports"),
+                match_fields: vec![
+                    MatchField {
+                        type_name: named("port_id_t"),
+                        ..match_field(1, "port", 9, match_field::MatchType::Exact)
+                    },
+                    MatchField {
+                        type_name: named("color_t"),
+                        ..match_field(2, "color", 8, match_field::MatchType::Exact)
+                    },
+                ],
+                ..Default::default()
+            }],
+            actions: vec![Action {
+                preamble: preamble(6, "MyIngress.set_port"),
+                params: vec![action::Param {
+                    id: 1,
+                    name: "port".to_owned(),
+                    bitwidth: 9,
+                    type_name: named("port_id_t"),
+                    ..Default::default()
+                }],
+            }],
+            type_info: Some(type_info),
+            ..Default::default()
+        };
+        let pipeconf = DefaultPipeconf::from_p4info("test", p4info, "").with_translation(
+            "port_id_t",
+            vec![(
+                Bytes::from_static(b"eth0"),
+                Bytes::from_static(&[0, 0, 1, 0]),
+            )],
+        );
+        let action = FlowTableAction::Action(FlowAction {
+            name: "MyIngress.set_port".into(),
+            params: Arc::new(smallvec![FlowActionParam {
+                name: "port".into(),
+                value: "eth0".encode(),
+            }]),
+        });
+        let build = |port: &str| {
+            let matches = [
+                FlowMatch {
+                    name: "color".into(),
+                    value: EXACT("GREEN"),
+                },
+                FlowMatch {
+                    name: "port".into(),
+                    value: EXACT(port),
+                },
+            ];
+            build_table_entry(
+                &pipeconf,
+                "MyIngress.ports",
+                &matches,
+                false,
+                &action,
+                0,
+                0,
+                0,
+            )
+        };
+
+        // port names are translated to the 32 bit port ids, enum members to their values.
+        let entry = build("eth0").unwrap();
+        let values: Vec<_> = entry
+            .r#match
+            .iter()
+            .map(|m| match m.field_match_type.as_ref() {
+                Some(FieldMatchType::Exact(Exact { value })) => value.clone(),
+                other => panic!("unexpected match {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![Bytes::from_static(&[2]), Bytes::from_static(&[1, 0])]
+        );
+        match entry.action.as_ref().and_then(|a| a.r#type.as_ref()) {
+            Some(crate::proto::p4runtime::table_action::Type::Action(action)) => {
+                assert_eq!(action.params[0].value.as_ref(), &[1, 0]);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+
+        // values read back are translated to the controller-side values.
+        let flow = table_entry_to_flow(&pipeconf, &entry).unwrap();
+        assert_eq!(flow.table.matches[0].value, EXACT("GREEN"));
+        assert_eq!(flow.table.matches[1].value, EXACT("eth0"));
+        match &flow.action {
+            FlowTableAction::Action(action) => {
+                assert_eq!(action.params[0].value, "eth0".encode())
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+
+        assert_eq!(
+            build("eth9"),
+            Err(ValidationError::Untranslatable {
+                name: "port".to_owned(),
+                type_name: "port_id_t".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_table_entry_to_flow_unknown_table() {
+        let pipeconf = DefaultPipeconf::from_p4info("test", test_p4info(), "");
+        let entry = TableEntry {
+            table_id: 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            table_entry_to_flow(&pipeconf, &entry).unwrap_err(),
+            ValidationError::UnknownId {
+                kind: "table",
+                id: 100
+            }
+        );
+
+        // the cause is reported, not a missing table.
+        let entry = TableEntry {
+            table_id: 1,
+            action: Some(TableAction {
+                r#type: Some(crate::proto::p4runtime::table_action::Type::Action(
+                    crate::proto::p4runtime::Action {
+                        action_id: 100,
+                        params: vec![],
+                    },
+                )),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            table_entry_to_flow(&pipeconf, &entry).unwrap_err(),
+            ValidationError::UnknownId {
+                kind: "action",
+                id: 100
+            }
+        );
+        let entry = TableEntry {
+            table_id: 1,
+            r#match: vec![FieldMatch {
+                field_id: 2,
+                field_match_type: Some(FieldMatchType::Exact(Exact {
+                    value: Bytes::from_static(&[4, 0]),
+                })),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            table_entry_to_flow(&pipeconf, &entry).unwrap_err(),
+            ValidationError::ValueTooWide {
+                name: "standard_metadata.ingress_port".to_owned(),
+                bitwidth: 9
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_write_errors() {
+        let errors = vec![
+            crate::proto::p4runtime::Error {
+                canonical_code: tonic::Code::Ok as i32,
+                ..Default::default()
+            },
+            crate::proto::p4runtime::Error {
+                canonical_code: tonic::Code::AlreadyExists as i32,
+                message: "Match entry exists".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let status = crate::proto::rpc::Status {
+            code: tonic::Code::Unknown as i32,
+            message: String::new(),
+            details: errors
+                .iter()
+                .map(|e| {
+                    let mut value = vec![];
+                    prost::Message::encode(e, &mut value).unwrap();
+                    crate::proto::rpc::Any {
+                        type_url: "type.googleapis.com/p4.v1.Error".to_owned(),
+                        value,
+                    }
+                })
+                .collect(),
+        };
+        let mut details = vec![];
+        prost::Message::encode(&status, &mut details).unwrap();
+        let status = tonic::Status::with_details(tonic::Code::Unknown, "", Bytes::from(details));
+
+        assert_eq!(decode_write_errors(&status), Some(errors));
+        assert_eq!(
+            decode_write_errors(&tonic::Status::new(tonic::Code::Unavailable, "")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_digest_data() {
+        use crate::proto::p4runtime::{p4_data::Data, P4Data, P4StructLike};
+
+        let bit = |bitwidth| P4DataTypeSpec {
+            type_spec: Some(p4_data_type_spec::TypeSpec::Bitstring(
+                P4BitstringLikeTypeSpec {
+                    type_spec: Some(p4_bitstring_like_type_spec::TypeSpec::Bit(P4BitTypeSpec {
+                        bitwidth,
+                    })),
+                    ..Default::default()
+                },
+            )),
+        };
+        let mut type_info = P4TypeInfo::default();
+        type_info.structs.insert(
+            "mac_learn_digest_t".to_owned(),
+            P4StructTypeSpec {
+                members: vec![
+                    p4_struct_type_spec::Member {
+                        name: "srcAddr".to_owned(),
+                        type_spec: Some(bit(48)),
+                    },
+                    p4_struct_type_spec::Member {
+                        name: "ingress_port".to_owned(),
+                        type_spec: Some(bit(9)),
+                    },
+                ],
+                ..Default::default()
+            },
+        );
+        let p4info = P4Info {
+            type_info: Some(type_info),
+            ..Default::default()
+        };
+        let digest = Digest {
+            preamble: preamble(3, "mac_learn_digest_t"),
+            type_spec: Some(P4DataTypeSpec {
+                type_spec: Some(p4_data_type_spec::TypeSpec::Struct(P4NamedType {
+                    name: "mac_learn_digest_t".to_owned(),
+                })),
+            }),
+        };
+        let data = P4Data {
+            data: Some(Data::Struct(P4StructLike {
+                members: vec![
+                    P4Data {
+                        data: Some(Data::Bitstring(Bytes::from_static(&[0, 0, 0, 0, 0, 1]))),
+                    },
+                    P4Data {
+                        data: Some(Data::Bitstring(Bytes::from_static(&[1]))),
+                    },
+                ],
+            })),
+        };
+
+        let members = decode_digest_data(&p4info, &digest, &data);
+        assert_eq!(members.len(), 2);
+        assert_eq!(members["srcAddr"].as_ref(), &[0, 0, 0, 0, 0, 1]);
+        assert_eq!(members["ingress_port"].as_ref(), &[1]);
+    }
+
+    #[test]
+    fn test_decode_p4data_bitstring() {
+        use crate::proto::p4runtime::{p4_data::Data, P4Data, P4StructLike};
+
+        let data = P4Data {
+            data: Some(Data::Bitstring(Bytes::from_static(&[1, 0]))),
+        };
+        assert_eq!(decode_p4data_bitstring::<u32>(&data), Some(256));
+        assert_eq!(decode_p4data_bitstring::<u8>(&data), None);
+        let data = P4Data {
+            data: Some(Data::Struct(P4StructLike { members: vec![] })),
+        };
+        assert_eq!(decode_p4data_bitstring::<u32>(&data), None);
+    }
+
+    #[test]
+    fn test_pipeline_config_cookie() {
+        let names = ["a_t", "b_t", "c_t", "d_t", "e_t", "f_t", "g_t", "h_t"];
+        let p4info = |names: Vec<&str>| {
+            let mut type_info = P4TypeInfo::default();
+            for name in names {
+                type_info
+                    .structs
+                    .insert(name.to_string(), P4StructTypeSpec::default());
+            }
+            P4Info {
+                type_info: Some(type_info),
+                ..test_p4info()
+            }
+        };
+        let p4info_a = p4info(names.to_vec());
+        let p4info_b = p4info(names.iter().rev().cloned().collect());
+
+        let cookie = pipeline_config_cookie(&p4info_a, b"{}");
+        assert_eq!(cookie, pipeline_config_cookie(&p4info_b, b"{}"));
+        assert_ne!(cookie, pipeline_config_cookie(&p4info_a, b"{ }"));
+        assert_ne!(cookie, pipeline_config_cookie(&test_p4info(), b"{}"));
+        // the cookie must not change with the toolchain.
+        assert_eq!(
+            pipeline_config_cookie(&P4Info::default(), b"{}"),
+            0x08f4_4b07_b590_1a25
+        );
+    }
+}
@@ -11,3 +11,25 @@
 //pub use rust_out::code as code;
 pub use rusty_p4_proto::proto::config::v1 as p4config;
 pub use rusty_p4_proto::proto::v1 as p4runtime;
+
+/// Messages from `google/rpc/status.proto`.
+/// A failed p4runtime `Write` carries one `p4.v1.Error` per update, packed in the details of this status.
+pub mod rpc {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Status {
+        #[prost(int32, tag = "1")]
+        pub code: i32,
+        #[prost(string, tag = "2")]
+        pub message: String,
+        #[prost(message, repeated, tag = "3")]
+        pub details: Vec<Any>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Any {
+        #[prost(string, tag = "1")]
+        pub type_url: String,
+        #[prost(bytes, tag = "2")]
+        pub value: Vec<u8>,
+    }
+}
@@ -1,10 +1,13 @@
+use crate::error::ValidationError;
 use crate::p4rt::pipeconf::{DefaultPipeconf, Pipeconf};
-use crate::p4rt::pure::build_table_entry;
+use crate::p4rt::pure::{build_table_entry, build_table_entry_match, table_entry_to_flow};
 use crate::proto::p4runtime::TableEntry;
 use crate::representation::DeviceID;
 use crate::util::value::{InnerValue, Value};
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::net::IpAddr;
@@ -12,48 +15,117 @@ use std::sync::Arc;
 
 pub type FlowMatches = Arc<SmallVec<[FlowMatch; 3]>>;
 
-#[derive(Debug, Hash, Clone)]
+/// A table entry by names, which is built against the P4Info of a pipeconf when written.
+/// In JSON, values are written as IPv4, IPv6 or MAC addresses by their width and as hex otherwise,
+/// see [crate::util::value::text].
+#[derive(Debug, Hash, Clone, Serialize, Deserialize)]
 pub struct Flow {
     pub table: FlowTable,
-    pub action: FlowAction,
+    pub action: FlowTableAction,
     pub priority: i32,
+    #[serde(default)]
     pub metadata: u64,
+    /// idle timeout of the entry in nanoseconds, 0 means the entry never expires.
+    /// The table must support idle timeout, see [crate::event::FlowIdleTimeout].
+    #[serde(default)]
+    pub idle_timeout_ns: i64,
 }
 
 impl Flow {
-    pub fn to_table_entry<T>(&self, pipeconf: &T, metadata: u64) -> TableEntry
+    /// Build the table entry of the flow, failing if the flow does not fit the P4Info of the pipeconf.
+    pub fn to_table_entry<T>(
+        &self,
+        pipeconf: &T,
+        metadata: u64,
+    ) -> Result<TableEntry, ValidationError>
     where
-        T: Pipeconf,
+        T: Pipeconf + ?Sized,
     {
-        let table_entry = build_table_entry(
-            pipeconf.get_p4info(),
-            self.table.name,
+        build_table_entry(
+            pipeconf,
+            &self.table.name,
             self.table.matches.as_ref(),
             false,
-            self.action.name,
-            self.action.params.as_ref(),
+            &self.action,
             self.priority,
             metadata,
-        );
-        table_entry
+            self.idle_timeout_ns,
+        )
+    }
+
+    /// Build the table entry of the flow with only the table id, match fields and priority,
+    /// see [build_table_entry_match](crate::p4rt::pure::build_table_entry_match).
+    /// The action of the flow is not checked, so a flow finds its entry whatever action was written.
+    pub fn to_table_entry_match<T>(&self, pipeconf: &T) -> Result<TableEntry, ValidationError>
+    where
+        T: Pipeconf + ?Sized,
+    {
+        build_table_entry_match(
+            pipeconf,
+            &self.table.name,
+            self.table.matches.as_ref(),
+            self.priority,
+        )
+    }
+
+    /// Rebuild a flow from a table entry read back from a device.
+    /// Fails if the entry refers to a table, match field, action or param that is not in the pipeconf.
+    /// Wildcard matches are omitted from table entries, so they are missing from the rebuilt flow,
+    /// see [InnerValue::DONT_CARE](crate::util::value::InnerValue::DONT_CARE).
+    pub fn from_table_entry<T>(
+        pipeconf: &T,
+        table_entry: &TableEntry,
+    ) -> Result<Flow, ValidationError>
+    where
+        T: Pipeconf + ?Sized,
+    {
+        table_entry_to_flow(pipeconf, table_entry)
     }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+/// The name of an object in P4Info: a table, match field, action or param of a flow,
+/// or an entity such as a counter, meter, register, action profile or digest.
+/// Names are borrowed when written as literals, e.g. in the `flow!` macro, and owned when built at runtime.
+pub type FlowName = Cow<'static, str>;
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct FlowTable {
-    pub name: &'static str,
+    pub name: FlowName,
+    /// sorted by name, as built by the `flow!` macro, so equal tables compare and hash equal.
+    #[serde(deserialize_with = "deserialize_matches")]
     pub matches: FlowMatches,
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+fn deserialize_matches<'de, D>(deserializer: D) -> Result<FlowMatches, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut matches = SmallVec::<[FlowMatch; 3]>::deserialize(deserializer)?;
+    matches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Arc::new(matches))
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FlowMatch {
-    pub name: &'static str,
+    pub name: FlowName,
     pub value: InnerValue,
 }
 
+impl FlowMatch {
+    pub fn new<N: Into<FlowName>>(name: N, value: InnerValue) -> FlowMatch {
+        FlowMatch {
+            name: name.into(),
+            value,
+        }
+    }
+}
+
 impl FlowTable {
-    pub fn new(name: &'static str, matches: FlowMatches) -> FlowTable {
-        FlowTable { name, matches }
+    pub fn new<N: Into<FlowName>>(name: N, matches: FlowMatches) -> FlowTable {
+        FlowTable {
+            name: name.into(),
+            matches,
+        }
     }
 
     pub fn merge_matches(&mut self, other: &SmallVec<[FlowMatch; 3]>) {
@@ -63,18 +135,68 @@ impl FlowTable {
     }
 }
 
-#[derive(Debug, Hash, Clone)]
+/// The action of a table entry.
+#[derive(Debug, Hash, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowTableAction {
+    /// A direct action.
+    Action(FlowAction),
+    /// A member id of the action profile of the table, see [crate::entity::action_profile::ActionProfileMember].
+    Member(u32),
+    /// A group id of the action selector of the table, see [crate::entity::action_profile::ActionProfileGroup].
+    Group(u32),
+    /// One shot action selector programming, the device creates the members and group of these actions.
+    ActionSet(Arc<SmallVec<[FlowWeightedAction; 3]>>),
+}
+
+impl From<FlowAction> for FlowTableAction {
+    fn from(action: FlowAction) -> Self {
+        FlowTableAction::Action(action)
+    }
+}
+
+#[derive(Debug, Hash, Clone, Serialize, Deserialize)]
 pub struct FlowAction {
-    pub name: &'static str,
+    pub name: FlowName,
     pub params: Arc<SmallVec<[FlowActionParam; 3]>>,
 }
 
-#[derive(Debug, Hash)]
+impl FlowAction {
+    pub fn new<N: Into<FlowName>>(
+        name: N,
+        params: Arc<SmallVec<[FlowActionParam; 3]>>,
+    ) -> FlowAction {
+        FlowAction {
+            name: name.into(),
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Hash, Clone, Serialize, Deserialize)]
+pub struct FlowWeightedAction {
+    pub action: FlowAction,
+    pub weight: i32,
+    /// the port watched for liveness, the action is not used if the port is down.
+    pub watch: i32,
+}
+
+#[derive(Debug, Hash, Clone, Serialize, Deserialize)]
 pub struct FlowActionParam {
-    pub name: &'static str,
+    pub name: FlowName,
+    #[serde(with = "crate::util::value::text")]
     pub value: Bytes,
 }
 
+impl FlowActionParam {
+    pub fn new<N: Into<FlowName>>(name: N, value: Bytes) -> FlowActionParam {
+        FlowActionParam {
+            name: name.into(),
+            value,
+        }
+    }
+}
+
 #[doc(hidden)]
 pub fn merge_matches(ours: &mut SmallVec<[FlowMatch; 3]>, other: &SmallVec<[FlowMatch; 3]>) {
     let len = ours.len();
@@ -83,5 +205,5 @@ pub fn merge_matches(ours: &mut SmallVec<[FlowMatch; 3]>, other: &SmallVec<[Flow
             ours.push(i.clone());
         }
     }
-    ours.sort_by(|a, b| a.name.cmp(b.name));
+    ours.sort_by(|a, b| a.name.cmp(&b.name));
 }
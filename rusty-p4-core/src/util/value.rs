@@ -31,12 +31,305 @@ pub fn RANGE<T: Encode, P: Encode>(v: T, p: P) -> InnerValue {
     InnerValue::RANGE(v.encode(), p.encode())
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub fn OPTIONAL<T: Encode>(v: T) -> InnerValue {
+    InnerValue::OPTIONAL(v.encode())
+}
+
+/// In JSON a value is tagged with its match kind, e.g. `{"exact": "10.0.0.1"}`,
+/// `{"lpm": "10.0.0.0/8"}` or `"dont_care"`. See [text] for the text of values.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ValueRepr", into = "ValueRepr")]
 pub enum InnerValue {
     EXACT(Bytes),
     LPM(Bytes, /*prefix_len*/ i32),
     TERNARY(Bytes, /*mask*/ Bytes),
     RANGE(/*low*/ Bytes, /*high*/ Bytes),
+    OPTIONAL(Bytes),
+    /// a wildcard, the field is omitted from the table entry.
+    /// Matches that are wildcards anyway, like an LPM with prefix length 0, a ternary with a zero mask
+    /// or a range over all values, are omitted too. Devices do not return omitted fields,
+    /// so such matches do not round-trip through [Flow::from_table_entry](crate::util::flow::Flow::from_table_entry).
+    DONT_CARE,
+}
+
+impl InnerValue {
+    /// Decode an exact value, e.g. a flow read back from a device.
+    pub fn decode_exact<T: Decode>(&self) -> Option<T> {
+        match self {
+            InnerValue::EXACT(v) => T::decode(v),
+            _ => None,
+        }
+    }
+
+    /// Decode a prefix, e.g. `decode_lpm::<Ipv6Addr>()`.
+    pub fn decode_lpm<T: Decode>(&self) -> Option<(T, i32)> {
+        match self {
+            InnerValue::LPM(v, prefix_len) => Some((T::decode(v)?, *prefix_len)),
+            _ => None,
+        }
+    }
+
+    /// Decode a ternary value and its mask.
+    pub fn decode_ternary<T: Decode>(&self) -> Option<(T, T)> {
+        match self {
+            InnerValue::TERNARY(v, mask) => Some((T::decode(v)?, T::decode(mask)?)),
+            _ => None,
+        }
+    }
+
+    /// Decode the low and high bounds of a range.
+    pub fn decode_range<T: Decode>(&self) -> Option<(T, T)> {
+        match self {
+            InnerValue::RANGE(low, high) => Some((T::decode(low)?, T::decode(high)?)),
+            _ => None,
+        }
+    }
+
+    pub fn decode_optional<T: Decode>(&self) -> Option<T> {
+        match self {
+            InnerValue::OPTIONAL(v) => T::decode(v),
+            _ => None,
+        }
+    }
+
+    /// The match kind of the value, as named in P4Info.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InnerValue::EXACT(_) => "Exact",
+            InnerValue::LPM(..) => "Lpm",
+            InnerValue::TERNARY(..) => "Ternary",
+            InnerValue::RANGE(..) => "Range",
+            InnerValue::OPTIONAL(_) => "Optional",
+            InnerValue::DONT_CARE => "DontCare",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ValueRepr {
+    Exact(#[serde(with = "text")] Bytes),
+    Lpm(LpmRepr),
+    Ternary {
+        #[serde(with = "text")]
+        value: Bytes,
+        #[serde(with = "text")]
+        mask: Bytes,
+    },
+    Range {
+        #[serde(with = "text")]
+        low: Bytes,
+        #[serde(with = "text")]
+        high: Bytes,
+    },
+    Optional(#[serde(with = "text")] Bytes),
+    DontCare,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LpmRepr {
+    /// only read, prefixes are written as text.
+    Prefix {
+        #[serde(with = "text")]
+        value: Bytes,
+        prefix_len: i32,
+    },
+    /// a prefix like `10.0.0.0/8` or `2001:db8::/32`.
+    Text(String),
+}
+
+impl From<InnerValue> for ValueRepr {
+    fn from(value: InnerValue) -> Self {
+        match value {
+            InnerValue::EXACT(v) => ValueRepr::Exact(v),
+            InnerValue::LPM(value, prefix_len) => ValueRepr::Lpm(LpmRepr::Text(format!(
+                "{}/{}",
+                format_value(&value),
+                prefix_len
+            ))),
+            InnerValue::TERNARY(value, mask) => ValueRepr::Ternary { value, mask },
+            InnerValue::RANGE(low, high) => ValueRepr::Range { low, high },
+            InnerValue::OPTIONAL(v) => ValueRepr::Optional(v),
+            InnerValue::DONT_CARE => ValueRepr::DontCare,
+        }
+    }
+}
+
+impl std::convert::TryFrom<ValueRepr> for InnerValue {
+    type Error = String;
+
+    fn try_from(value: ValueRepr) -> Result<Self, Self::Error> {
+        Ok(match value {
+            ValueRepr::Exact(v) => InnerValue::EXACT(v),
+            ValueRepr::Lpm(LpmRepr::Prefix { value, prefix_len }) => {
+                InnerValue::LPM(value, prefix_len)
+            }
+            ValueRepr::Lpm(LpmRepr::Text(prefix)) => {
+                let (value, prefix_len) =
+                    parse_prefix(&prefix).ok_or_else(|| format!("invalid prefix '{}'", prefix))?;
+                InnerValue::LPM(value, prefix_len)
+            }
+            ValueRepr::Ternary { value, mask } => InnerValue::TERNARY(value, mask),
+            ValueRepr::Range { low, high } => InnerValue::RANGE(low, high),
+            ValueRepr::Optional(v) => InnerValue::OPTIONAL(v),
+            ValueRepr::DontCare => InnerValue::DONT_CARE,
+        })
+    }
+}
+
+/// Encode a big-endian value as the P4Runtime canonical bytestring of a field with `bitwidth` bits:
+/// the shortest representation without leading zero bytes, and a single zero byte for zero.
+/// Returns `None` if the value has bits set above the bitwidth.
+pub fn canonical_bytestring(value: &[u8], bitwidth: i32) -> Option<Bytes> {
+    let start = value
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or(value.len().saturating_sub(1));
+    let value = &value[start..];
+    if value.is_empty() {
+        return Some(Bytes::from_static(&[0]));
+    }
+    let bits = (value.len() - 1) * 8 + (8 - value[0].leading_zeros() as usize);
+    if bits > bitwidth.max(0) as usize {
+        return None;
+    }
+    Some(Bytes::copy_from_slice(value))
+}
+
+/// Decode a bytestring read from a device, which is usually canonical,
+/// to the full byte width of a field with `bitwidth` bits.
+/// Returns `None` if the value has bits set above the bitwidth.
+pub fn decode_bytestring(value: &[u8], bitwidth: i32) -> Option<Bytes> {
+    let value = canonical_bytestring(value, bitwidth)?;
+    let bytes_len = (bitwidth.max(0) as usize + 7) / 8;
+    if value.len() >= bytes_len {
+        return Some(value);
+    }
+    let mut padded = BytesMut::with_capacity(bytes_len);
+    padded.extend_from_slice(&vec![0u8; bytes_len - value.len()]);
+    padded.extend_from_slice(&value);
+    Some(padded.freeze())
+}
+
+/// The text of a value by its width: an IPv4 address for 4 bytes, a MAC address for 6 bytes,
+/// an IPv6 address for 16 bytes, and `0x` hex otherwise. Values read from a device have the full width
+/// of their field, so a 32-bit field is written as an IPv4 address and a 48-bit field as a MAC address.
+pub fn format_value(value: &[u8]) -> String {
+    use std::fmt::Write;
+
+    match value.len() {
+        4 => Ipv4Addr::decode(value).unwrap().to_string(),
+        16 => Ipv6Addr::decode(value).unwrap().to_string(),
+        6 => {
+            let mut text = String::with_capacity(17);
+            for (i, b) in value.iter().enumerate() {
+                if i > 0 {
+                    text.push(':');
+                }
+                write!(text, "{:02x}", b).unwrap();
+            }
+            text
+        }
+        _ => {
+            let mut text = String::with_capacity(2 + value.len() * 2);
+            text.push_str("0x");
+            for b in value.iter() {
+                write!(text, "{:02x}", b).unwrap();
+            }
+            text
+        }
+    }
+}
+
+/// Parse the text of a value: `0x` hex, a decimal number, an IPv4 or IPv6 address, or a MAC address.
+/// Hex keeps its leading zero bytes, e.g. `0x0800` is two bytes, and a bare `0x` is the empty value
+/// written by [format_value]. Numbers are canonical bytestrings.
+pub fn parse_value(text: &str) -> Option<Bytes> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        return parse_hex(hex);
+    }
+    if let Ok(number) = text.parse::<u128>() {
+        return canonical_bytestring(&number.to_be_bytes(), 128);
+    }
+    if let Ok(ip) = text.parse::<IpAddr>() {
+        return Some(ip.encode());
+    }
+    parse_mac(text).map(|mac| Bytes::copy_from_slice(&mac))
+}
+
+/// Parse a prefix like `10.0.0.0/8` into its value and prefix length.
+pub fn parse_prefix(text: &str) -> Option<(Bytes, i32)> {
+    let mut parts = text.splitn(2, '/');
+    let value = parse_value(parts.next()?)?;
+    let prefix_len = parts.next()?.parse().ok()?;
+    Some((value, prefix_len))
+}
+
+fn parse_hex(hex: &str) -> Option<Bytes> {
+    let digits = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    // an odd number of digits is padded with a leading zero.
+    let padding = digits.len() % 2;
+    let mut bytes = vec![0u8; (digits.len() + 1) / 2];
+    for (i, digit) in digits.into_iter().enumerate() {
+        let position = i + padding;
+        bytes[position / 2] |= digit << (4 * (1 - position % 2));
+    }
+    Some(Bytes::from(bytes))
+}
+
+fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = text.split(|c| c == ':' || c == '-');
+    for byte in mac.iter_mut() {
+        let part = parts.next()?;
+        if part.is_empty() || part.len() > 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(mac)
+}
+
+/// Serde of values as text, for `#[serde(with = "text")]`.
+/// Values are written with [format_value], and read with [parse_value] or from a JSON number.
+pub mod text {
+    use super::{canonical_bytestring, format_value, parse_value};
+    use bytes::Bytes;
+    use serde::de::{self, Unexpected, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_value(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+
+    struct ValueVisitor;
+
+    impl<'de> Visitor<'de> for ValueVisitor {
+        type Value = Bytes;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a hex, decimal, IP address or MAC address value")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Bytes, E> {
+            Ok(canonical_bytestring(&v.to_be_bytes(), 64).unwrap())
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Bytes, E> {
+            parse_value(v).ok_or_else(|| E::invalid_value(Unexpected::Str(v), &self))
+        }
+    }
 }
 
 pub fn encode<T: Encode>(v: T) -> InnerParamValue {
@@ -105,3 +398,327 @@ impl Encode for MAC {
         Bytes::copy_from_slice(self.0.as_ref())
     }
 }
+
+impl Encode for u64 {
+    fn encode(self) -> Bytes {
+        Bytes::copy_from_slice(self.to_be_bytes().as_ref())
+    }
+}
+
+impl Encode for u128 {
+    fn encode(self) -> Bytes {
+        Bytes::copy_from_slice(self.to_be_bytes().as_ref())
+    }
+}
+
+impl Encode for bool {
+    fn encode(self) -> Bytes {
+        Bytes::copy_from_slice(&[self as u8])
+    }
+}
+
+impl<const N: usize> Encode for [u8; N] {
+    fn encode(self) -> Bytes {
+        Bytes::copy_from_slice(self.as_ref())
+    }
+}
+
+/// An unsigned value of a field with `N` bits, e.g. `Bits<48>` for a 48-bit field.
+/// `N` is at most 128, there is no value of a wider `Bits`.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Bits<const N: usize>(u128);
+
+impl<const N: usize> Bits<N> {
+    /// `None` if the value does not fit in `N` bits, or `N` is more than 128.
+    pub fn new(value: u128) -> Option<Bits<N>> {
+        if N > 128 || (N < 128 && value >> N != 0) {
+            None
+        } else {
+            Some(Bits(value))
+        }
+    }
+
+    pub fn value(self) -> u128 {
+        self.0
+    }
+}
+
+impl<const N: usize> Encode for Bits<N> {
+    fn encode(self) -> Bytes {
+        let bytes = self.0.to_be_bytes();
+        Bytes::copy_from_slice(&bytes[16 - (N + 7) / 8..])
+    }
+}
+
+pub fn decode<T: Decode>(value: &[u8]) -> Option<T> {
+    T::decode(value)
+}
+
+/// Decode a big-endian value read back from a device.
+/// The value may be shorter than the type, as devices return canonical bytestrings,
+/// but must not have set bits the type cannot hold.
+pub trait Decode: Sized {
+    fn decode(value: &[u8]) -> Option<Self>;
+}
+
+/// Left pad a big-endian value to `N` bytes, `None` if it has more than `N` significant bytes.
+fn pad_to<const N: usize>(value: &[u8]) -> Option<[u8; N]> {
+    let start = value.iter().position(|b| *b != 0).unwrap_or(value.len());
+    let significant = &value[start..];
+    if significant.len() > N {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    bytes[N - significant.len()..].copy_from_slice(significant);
+    Some(bytes)
+}
+
+impl Decode for u8 {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(u8::from_be_bytes)
+    }
+}
+
+impl Decode for u16 {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(u16::from_be_bytes)
+    }
+}
+
+impl Decode for u32 {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(u32::from_be_bytes)
+    }
+}
+
+impl Decode for i32 {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(i32::from_be_bytes)
+    }
+}
+
+impl Decode for u64 {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(u64::from_be_bytes)
+    }
+}
+
+impl Decode for u128 {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(u128::from_be_bytes)
+    }
+}
+
+impl Decode for bool {
+    fn decode(value: &[u8]) -> Option<Self> {
+        match u8::decode(value)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value)
+    }
+}
+
+impl Decode for Ipv4Addr {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to::<4>(value).map(Ipv4Addr::from)
+    }
+}
+
+impl Decode for Ipv6Addr {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to::<16>(value).map(Ipv6Addr::from)
+    }
+}
+
+impl Decode for MAC {
+    fn decode(value: &[u8]) -> Option<Self> {
+        pad_to(value).map(ipip::MAC)
+    }
+}
+
+impl Decode for String {
+    fn decode(value: &[u8]) -> Option<Self> {
+        String::from_utf8(value.to_vec()).ok()
+    }
+}
+
+impl<const N: usize> Decode for Bits<N> {
+    fn decode(value: &[u8]) -> Option<Self> {
+        Bits::new(u128::decode(value)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_canonical_bytestring() {
+        assert_eq!(
+            canonical_bytestring(&[0, 0, 0, 2], 9).unwrap().as_ref(),
+            &[2]
+        );
+        assert_eq!(
+            canonical_bytestring(&[0, 0, 1, 2], 9).unwrap().as_ref(),
+            &[1, 2]
+        );
+        assert_eq!(canonical_bytestring(&[0, 0], 9).unwrap().as_ref(), &[0]);
+        assert_eq!(canonical_bytestring(&[], 9).unwrap().as_ref(), &[0]);
+        assert_eq!(canonical_bytestring(&[2, 0], 9), None);
+        assert_eq!(canonical_bytestring(&[1, 0, 0, 0, 0], 32), None);
+        assert_eq!(
+            canonical_bytestring(&[10, 0, 0, 0], 32).unwrap().as_ref(),
+            &[10, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(u64::decode(&0x0102u64.encode()), Some(0x0102));
+        assert_eq!(u16::decode(&[2]), Some(2));
+        assert_eq!(u16::decode(&[1, 0, 0]), None);
+        assert_eq!(u128::decode(&u128::MAX.encode()), Some(u128::MAX));
+        assert_eq!(true.encode().as_ref(), &[1]);
+        assert_eq!(bool::decode(&[0]), Some(false));
+        assert_eq!(bool::decode(&[2]), None);
+        assert_eq!(<[u8; 3]>::decode(&[1]), Some([0, 0, 1]));
+        assert_eq!(
+            MAC::decode(&[1, 2, 3, 4, 5, 6]),
+            Some(ipip::MAC([1, 2, 3, 4, 5, 6]))
+        );
+
+        let ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        assert_eq!(Ipv6Addr::decode(&ip.encode()), Some(ip));
+        assert_eq!(LPM(ip, 64).decode_lpm::<Ipv6Addr>(), Some((ip, 64)));
+        assert_eq!(LPM(ip, 64).decode_exact::<Ipv6Addr>(), None);
+
+        let bits = Bits::<48>::new(0x0102_0304_0506).unwrap();
+        assert_eq!(bits.encode().as_ref(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(Bits::<48>::decode(&[1, 2, 3, 4, 5, 6]), Some(bits));
+        assert_eq!(Bits::<9>::new(512), None);
+        assert_eq!(Bits::<9>::decode(&[2, 0]), None);
+        assert_eq!(Bits::<129>::new(1), None);
+        assert_eq!(Bits::<129>::decode(&[1]), None);
+        assert_eq!(
+            Bits::<128>::new(u128::MAX).map(Bits::value),
+            Some(u128::MAX)
+        );
+    }
+
+    #[test]
+    fn test_decode_bytestring() {
+        assert_eq!(decode_bytestring(&[2], 9).unwrap().as_ref(), &[0, 2]);
+        assert_eq!(
+            decode_bytestring(&[0, 0, 0, 2], 9).unwrap().as_ref(),
+            &[0, 2]
+        );
+        assert_eq!(
+            decode_bytestring(&[1], 48).unwrap().as_ref(),
+            &[0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(decode_bytestring(&[4, 0], 9), None);
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let bytes = |b: &'static [u8]| Some(Bytes::from_static(b));
+        assert_eq!(parse_value("0x0800"), bytes(&[8, 0]));
+        assert_eq!(parse_value("0x800"), bytes(&[8, 0]));
+        assert_eq!(parse_value("0x"), bytes(&[]));
+        assert_eq!(parse_value("0xg"), None);
+        assert_eq!(parse_value("2048"), bytes(&[8, 0]));
+        assert_eq!(parse_value("0"), bytes(&[0]));
+        assert_eq!(parse_value("10.0.0.1"), bytes(&[10, 0, 0, 1]));
+        assert_eq!(parse_value("::1").map(|v| v.len()), Some(16));
+        assert_eq!(
+            parse_value("00:11:22:aa:bb:cc"),
+            bytes(&[0, 0x11, 0x22, 0xaa, 0xbb, 0xcc])
+        );
+        assert_eq!(parse_value("00:11:22:aa:bb"), None);
+        assert_eq!(parse_value("port1"), None);
+        assert_eq!(
+            parse_prefix("10.1.0.0/16"),
+            Some((Bytes::from_static(&[10, 1, 0, 0]), 16))
+        );
+        assert_eq!(parse_prefix("10.1.0.0"), None);
+    }
+
+    #[test]
+    fn test_format_value_round_trip() {
+        let values: [&[u8]; 6] = [
+            &[],
+            &[3],
+            &[8, 0],
+            &[10, 0, 0, 1],
+            &[0, 0x11, 0x22, 0xaa, 0xbb, 0xcc],
+            &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        ];
+        for value in values.iter() {
+            assert_eq!(
+                parse_value(&format_value(value)).as_deref(),
+                Some(*value),
+                "{}",
+                format_value(value)
+            );
+        }
+    }
+
+    #[test]
+    fn test_value_serde() {
+        let values = vec![
+            EXACT(0x0800u16),
+            LPM(Ipv4Addr::new(10, 0, 0, 0), 8),
+            TERNARY(0x0800u16, 0xff00u16),
+            RANGE(1u16, 1024u16),
+            OPTIONAL(3u8),
+            InnerValue::DONT_CARE,
+        ];
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"exact":"0x0800"},{"lpm":"10.0.0.0/8"},{"ternary":{"value":"0x0800","mask":"0xff00"}},{"range":{"low":"0x0001","high":"0x0400"}},{"optional":"0x03"},"dont_care"]"#
+        );
+        let parsed: Vec<InnerValue> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, values);
+
+        // values are written as addresses by their width.
+        let ip: Ipv6Addr = "2001:db8::".parse().unwrap();
+        let values = vec![
+            EXACT(Ipv4Addr::new(10, 0, 0, 1)),
+            EXACT(ipip::MAC([0, 0x11, 0x22, 0xaa, 0xbb, 0xcc])),
+            EXACT(ip),
+            LPM(ip, 32),
+            TERNARY(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(255, 0, 0, 0)),
+            LPM(0x0800u16, 8),
+        ];
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"exact":"10.0.0.1"},{"exact":"00:11:22:aa:bb:cc"},{"exact":"2001:db8::"},{"lpm":"2001:db8::/32"},{"ternary":{"value":"10.0.0.0","mask":"255.0.0.0"}},{"lpm":"0x0800/8"}]"#
+        );
+        let parsed: Vec<InnerValue> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, values);
+
+        let parsed: Vec<InnerValue> = serde_json::from_str(
+            r#"[{"exact": "00:11:22:33:44:55"}, {"lpm": {"value": "0x0a000000", "prefix_len": 8}}, {"optional": 3}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                EXACT(ipip::MAC([0, 0x11, 0x22, 0x33, 0x44, 0x55])),
+                LPM(Ipv4Addr::new(10, 0, 0, 0), 8),
+                OPTIONAL(3u8),
+            ]
+        );
+        assert!(serde_json::from_str::<InnerValue>(r#"{"lpm": "10.0.0.0"}"#).is_err());
+        assert!(serde_json::from_str::<InnerValue>(r#"{"exact": "port1"}"#).is_err());
+    }
+}
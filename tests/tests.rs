@@ -100,6 +100,25 @@ fn test_flow() {
     };
 }
 
+#[test]
+fn test_flow_idle_timeout() {
+    let flow = flow! {
+        table: "efg" {
+            "abcd1" => 1u32,
+        },
+        idle_timeout: 10_000_000_000,
+        action: "Hi",
+    };
+    assert_eq!(flow.idle_timeout_ns, 10_000_000_000);
+    let flow = flow! {
+        table: "efg" {
+            "abcd1" => 1u32,
+        },
+        action: "Hi",
+    };
+    assert_eq!(flow.idle_timeout_ns, 0);
+}
+
 #[test]
 fn test_flow_merge() {
     let whatever = flow_match! {
@@ -122,3 +141,46 @@ fn test_flow_merge() {
     };
     dbg!(result);
 }
+
+#[test]
+fn test_flow_action_profile() {
+    let flow = flow! {
+        pipe:"abcd",
+        table: "efg" {
+            "abcd1" => 1u32,
+        },
+        member: 1,
+    };
+    assert!(matches!(
+        flow.action,
+        rusty_p4::util::flow::FlowTableAction::Member(1)
+    ));
+    let group_id = 2;
+    let flow = flow! {
+        pipe:"abcd",
+        table: "efg" {
+            "abcd1" => 1u32,
+        },
+        group: group_id,
+    };
+    assert!(matches!(
+        flow.action,
+        rusty_p4::util::flow::FlowTableAction::Group(2)
+    ));
+}
+
+#[test]
+fn test_optional_and_dont_care() {
+    let port = 2u16;
+    let matches = flow_match! {
+        "abcd1" => ?port,
+        "abcd2" => _,
+        "abcd3" => ?1u32,
+    };
+    assert_eq!(matches[0].value, rusty_p4::util::value::OPTIONAL(2u16));
+    assert_eq!(
+        matches[1].value,
+        rusty_p4::util::value::InnerValue::DONT_CARE
+    );
+    assert_eq!(matches[2].value, rusty_p4::util::value::OPTIONAL(1u32));
+}